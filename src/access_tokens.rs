@@ -16,11 +16,12 @@ static TWITCH_SCOPE: &[&str] = &[
   "chat:edit",                  // Send live stream chat messages
   "chat:read",                  // View live stream chat messages
   "moderator:manage:banned_users", // Ban and unban users
+  "moderator:manage:chat_messages", // Delete chat messages
   "moderator:manage:shoutouts", // Manage a broadcaster’s shoutouts
   "moderator:read:chatters",    // View the chatters in a broadcaster’s chat room
   "moderator:read:followers",   // Read the followers of a broadcaster
-  "whispers:edit",              // Send whisper messages
-  "whispers:read",              // View your whisper messages
+  "user:manage:whispers",       // Send whisper messages
+  "user:read:whispers",         // Receive whisper messages
 ];
 
 /// Updates access tokens
@@ -165,6 +166,15 @@ fn twitch_get_new(id: &String, pass: &String) {
   }
 }
 
+/// Refreshes Twitch access token using stored refresh token. Returns true if new token was acquired, otherwise false.
+pub fn refresh_twitch() -> bool {
+  return twitch_refresh(
+    &secrets::get_data(secrets::Keys::TwitchID),
+    &secrets::get_data(secrets::Keys::TwitchPassowrd),
+    &database::get_data(database::Keys::TwitchOAuthRefresh),
+  );
+}
+
 /// Refreshes the access tokens. Returns true if new token was acquired, otherwise false.
 fn twitch_refresh(id: &String, pass: &String, refresh_token: &String) -> bool {
   log::info!("Refreshing Twitch access token");
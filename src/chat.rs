@@ -1,51 +1,64 @@
 use std::{
-  collections::VecDeque,
-  io::{Read, Write},
-  net::TcpStream,
+  io::{self, ErrorKind, Read, Write},
   sync::Mutex,
   thread::{self},
-  time::{Duration, SystemTime},
+  time::{Duration, Instant},
 };
 
-use crate::{database, secrets};
+use crate::{access_tokens, database, helix, points, scripts, secrets, template, tts};
 
-/// Message metadata
-struct Metadata {
-  message_type: String,
-  badge: String,
-  username: String,
-  user_id: String,
-  message_id: String,
-  custrom_reward_id: String,
-  bits: String,
-  msg_id: String,
-}
+mod commands;
+mod connection;
+pub mod counters;
+pub mod emotes;
+mod games;
+pub mod greetings;
+pub mod history;
+mod limiter;
+mod message;
+pub mod mod_commands;
+mod moderation;
+mod poll;
+mod quotes;
+mod raffle;
+pub mod room_state;
+pub mod shoutout;
+mod timers;
+mod whispers;
 
-impl Metadata {
-  fn clear(&mut self) {
-    self.message_type.clear();
-    self.badge.clear();
-    self.username.clear();
-    self.user_id.clear();
-    self.message_id.clear();
-    self.custrom_reward_id.clear();
-    self.bits.clear();
-    self.msg_id.clear();
-  }
-}
+pub use commands::{check_access, exists as command_exists, is_builtin};
+use connection::{Backoff, ChatStream};
+pub use limiter::Priority;
+pub use message::{ChatMessage, Permission, Privmsg};
+pub use moderation::is_banned;
+use room_state::RoomStateUpdate;
+pub use whispers::handle_whisper;
 
 /// Should chat messages be printed to console window?
 const PRINT_CHAT_MESSAGES: bool = false;
-/// PING message response
-const PONG: &[u8] = b"PONG :tmi.twitch.tv\r\n";
-/// Queue for messages that should be send
-static SENDQUEUE: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
-/// Minimum time between sending messages
-const SEND_TIMEOUT: Duration = Duration::from_millis(100);
+/// State of joined channel
+struct ChannelState {
+  name: String,
+  room_id: String,
+}
+
+/// States of joined channels
+static CHANNELS: Mutex<Vec<ChannelState>> = Mutex::new(Vec::new());
+/// Read timeout of the connection, the send queue is checked at least that often
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Time without any received data after which the connection is considered dead
+const STALE_TIMEOUT: Duration = Duration::from_secs(7 * 60);
 
 /// Starts the chat bot
 pub fn start() {
   log::info!("Chat bot start");
+  commands::load();
+  moderation::load();
+  counters::load();
+  timers::load();
+  raffle::load();
+  emotes::load();
+  games::load();
 
   // Create chat bot thread
   thread::Builder::new()
@@ -56,415 +69,609 @@ pub fn start() {
     .expect("Spawning chat bot thread failed!");
 }
 
+/// Reason of closing the chat connection
+enum Disconnect {
+  /// Server asked to reconnect (RECONNECT message)
+  Reconnect,
+  /// Server closed the connection
+  Closed,
+  /// Server rejected the access token
+  LoginFailed,
+  /// Connection error
+  Error(String),
+}
+
 fn update() {
-  let channel = secrets::get_data(secrets::Keys::Channel);
-  if channel.len() == 0 {
+  let channels = channels();
+  if channels.len() == 0 {
     log::error!("Missing channel name");
     return;
   }
-  let (mut twitch_name, mut twitch_oauth) = (String::new(), String::new());
-  let mut buffer = [0u8; 16384]; // Max IRC message is 4096 bytes? let's allocate 4 times that, 2 times max message length wasn't enaugh for really fast chats
-  let mut temp: usize;
-  let (mut msg_start, mut msg_end): (usize, usize);
-  let mut index: Option<usize>;
-  let (mut msg, mut header, mut body): (&str, &str, &str);
-  let mut last_send_time = SystemTime::now();
-  let timeout_error = Duration::from_secs(2);
-  let mut metadata: Metadata = Metadata {
-    message_type: String::new(),
-    badge: String::new(),
-    username: String::new(),
-    user_id: String::new(),
-    message_id: String::new(),
-    custrom_reward_id: String::new(),
-    bits: String::new(),
-    msg_id: String::new(),
-  };
-  let mut data = String::new();
+  let mut backoff = Backoff::new();
+  let mut refresh_token = false;
 
   loop {
-    let client = TcpStream::connect("irc.chat.twitch.tv:6667");
-    if client.is_err() {
-      log::error!("Chat bot connection error: {}", client.unwrap_err());
-      thread::sleep(timeout_error);
-    } else {
-      log::info!("Chat bot connected");
-
-      twitch_name.clear();
-      twitch_name.push_str(&secrets::get_data(secrets::Keys::TwitchName));
-      twitch_oauth.clear();
-      twitch_oauth.push_str(&database::get_data(database::Keys::TwitchOAuth));
-
-      let mut stream = client.unwrap();
-      stream
-        .set_read_timeout(Some(SEND_TIMEOUT))
-        .expect("Something went wrong when setting read timeout");
-      stream
-        .write(format!("PASS oauth:{twitch_oauth}\r\n").as_bytes())
-        .expect("Something went wrong when sending the message");
-      stream
-        .write(format!("NICK {twitch_name}\r\n").as_bytes())
-        .expect("Something went wrong when sending the message");
-      stream
-        .write(format!("JOIN #{channel},#{channel}\r\n").as_bytes())
-        .expect("Something went wrong when sending the message");
-      stream
-        .write("CAP REQ :twitch.tv/commands twitch.tv/tags\r\n".as_bytes())
-        .expect("Something went wrong when sending the message");
+    if let Some(delay) = backoff.next_delay() {
+      log::info!("Chat bot reconnecting in {:.1} s", delay.as_secs_f32());
+      thread::sleep(delay);
+    }
 
-      loop {
-        // Receive
-        let res = stream.read(&mut buffer);
-        if res.is_ok() {
-          temp = res.unwrap();
-          if temp > 0 {
-            msg_start = 0;
-            // data is whole received message, it may contain multiple messages
-            let d = String::from_utf8_lossy(&buffer[..temp]).into_owned(); // lossy conversion is needed because if the data contained a char outside of utf-8 range it will crash the program
-            data.push_str(&d);
-            drop(d);
+    // The access token could expire while the bot was connected, get new one before logging in again
+    if refresh_token && access_tokens::refresh_twitch() {
+      refresh_token = false;
+    }
 
-            // Loop through every message in data
-            loop {
-              if msg_start >= data.len() {
-                data.clear();
-                break;
-              }
+    let mut stream = match ChatStream::connect(READ_TIMEOUT) {
+      Ok(stream) => stream,
+      Err(err) => {
+        log::error!("Chat bot connection error: {}", err);
+        backoff.failed();
+        continue;
+      }
+    };
+    log::info!("Chat bot connected");
 
-              // Find the end of the message
-              index = data[msg_start..].find("\r\n");
-              msg_end = match index {
-                Some(idx) => idx,
-                None => {
-                  let temp = String::from(&data[msg_start..]);
-                  data.clear();
-                  data.push_str(&temp);
-                  break;
-                }
-              } + msg_start;
-              if msg_end > data.len() {
-                msg_end = data.len();
-              }
+    let reason = session(&mut stream, &channels, &mut backoff);
+    stream.shutdown();
+    refresh_token = true;
+    match reason {
+      Disconnect::Reconnect => {
+        log::warn!("Chat bot got reconnect request from the server");
+      }
+      Disconnect::Closed => {
+        log::warn!("Chat bot connection was closed by the server");
+        backoff.failed();
+      }
+      Disconnect::LoginFailed => {
+        log::error!("Chat bot login failed, the access token was rejected");
+        backoff.failed();
+      }
+      Disconnect::Error(err) => {
+        log::error!("Chat bot connection error: {}", err);
+        backoff.failed();
+      }
+    }
+  }
+}
 
-              // Get the message
-              msg = &data[msg_start..msg_end];
+/// Logs in, joins the channels and handles the connection until it gets disconnected
+fn session(stream: &mut ChatStream, channels: &[String], backoff: &mut Backoff) -> Disconnect {
+  let twitch_name = secrets::get_data(secrets::Keys::TwitchName);
+  let twitch_oauth = database::get_data(database::Keys::TwitchOAuth);
+  let mut buffer = [0u8; 16384]; // Max IRC message is 4096 bytes? let's allocate 4 times that, 2 times max message length wasn't enaugh for really fast chats
+  let mut data = String::new();
+  let mut last_received = Instant::now();
 
-              if msg.starts_with("PING") {
-                stream
-                  .write(PONG)
-                  .expect("Something went wrong when sending the message");
-              } else {
-                (header, body) = parse_message(&msg, &mut metadata);
+  for line in [
+    "CAP REQ :twitch.tv/commands twitch.tv/tags".to_string(),
+    format!("PASS oauth:{twitch_oauth}"),
+    format!("NICK {twitch_name}"),
+    format!(
+      "JOIN {}",
+      channels
+        .iter()
+        .map(|c| format!("#{}", c))
+        .collect::<Vec<String>>()
+        .join(",")
+    ),
+  ] {
+    if let Err(err) = stream.write_all(format!("{}\r\n", line).as_bytes()) {
+      return Disconnect::Error(err.to_string());
+    }
+  }
 
-                match metadata.message_type.as_str() {
-                  "PRIVMSG" => {
-                    if metadata.custrom_reward_id.len() > 0 {
-                      println!(
-                        "> {} redeemed custom reward with ID: {}. {}",
-                        metadata.username, metadata.custrom_reward_id, body
-                      );
-                    } else if metadata.bits.len() > 0 {
-                      println!(
-                        "> {} cheered with {} bits. {}",
-                        metadata.username, metadata.bits, body
-                      );
-                    } else {
-                      if PRINT_CHAT_MESSAGES {
-                        println!("{:^3} {:>20}: {}", metadata.badge, metadata.username, body);
-                      }
-                      check_for_commands(&metadata, body);
-                    }
-                  }
-                  "USERNOTICE" => {
-                    match metadata.msg_id.as_str() {
-                      "sub" => {
-                        println!("> {} subscribed! {}", metadata.username, body);
-                      }
-                      "resub" => {
-                        println!("> {} resubscribed! {}", metadata.username, body);
-                      }
-                      "subgift" => {
-                        let mut receipent = "";
-                        index = header.find("msg-param-recipient-display-name=");
-                        if index.is_some() {
-                          temp = index.unwrap() + 33; // 33 == "msg-param-recipient-display-name=".len()
-                          index = header[temp..].find(';');
-                          if index.is_some() {
-                            receipent = &header[temp..(index.unwrap() + temp)];
-                          }
-                        }
-                        println!(
-                          "> {} gifted sub to {}! {}",
-                          metadata.username, receipent, body
-                        );
-                      }
-                      "submysterygift" => {
-                        println!(
-                          "> {} gifted some subs to random viewers! {}",
-                          metadata.username, body
-                        );
-                      }
-                      "primepaidupgrade" => {
-                        println!(
-                          "> {} converted prime sub to standard sub! {}",
-                          metadata.username, body
-                        );
-                      }
-                      "giftpaidupgrade" => {
-                        println!(
-                          "> {} continuing sub gifted by another chatter! {}",
-                          metadata.username, body
-                        );
-                      }
-                      "communitypayforward" => {
-                        println!(
-                          "> {} is paying forward sub gifted by another chatter! {}",
-                          metadata.username, body
-                        );
-                      }
-                      "announcement" => {
-                        println!("> {} announced that {}", metadata.username, body);
-                      }
-                      "raid" => {
-                        println!("> {} raided the channel! {}", metadata.username, body);
-                      }
-                      "viewermilestone" => {
-                        println!(
-                          "> {} did something that fired viewer milestone! {}",
-                          metadata.username, body
-                        );
-                      }
-                      _ => {
-                        // Message type not recognized - print the whole message
-                        println!("{}", msg);
-                      }
-                    }
-                  }
-                  "CLEARCHAT" => {
-                    if msg.starts_with("@ban-duration") {
-                      index = msg.rfind(':');
-                      if index.is_some() {
-                        temp = index.unwrap() + 1;
-                      } else {
-                        temp = msg.len();
-                      }
-                      println!("> {} got banned!", &msg[temp..]);
-                    } else if body.len() > 0 {
-                      println!("> {} chat messages got cleared", &body);
-                    } else {
-                      println!("> Chat got cleared");
-                    }
-                  }
-                  "CLEARMSG" => {
-                    if msg.starts_with("@login=") {
-                      index = msg.find(';');
-                      if index.is_some() {
-                        temp = index.unwrap();
-                      } else {
-                        temp = msg.len();
-                      }
-                      println!("> {} got perma banned!", &msg[7..temp]);
-                    } else {
-                      println!("> Someones messages got cleared")
-                    }
-                  }
-                  "NOTICE" => {
-                    match metadata.msg_id.as_str() {
-                      "emote_only_on" => {
-                        println!("> This room is now in emote-only mode.");
-                      }
-                      "emote_only_off" => {
-                        println!("> This room is no longer in emote-only mode.");
-                      }
-                      "subs_on" => {
-                        println!("> This room is now in subscribers-only mode.");
-                      }
-                      "subs_off" => {
-                        println!("> This room is no longer in subscribers-only mode.");
-                      }
-                      "followers_on" | "followers_on_zero" => {
-                        println!("> This room is now in followers-only mode.");
-                      }
-                      "followers_off" => {
-                        println!("> This room is no longer in followers-only mode.");
-                      }
-                      "slow_on" => {
-                        println!("> This room is now in slow mode.");
-                      }
-                      "slow_off" => {
-                        println!("> This room is no longer in slow mode.");
-                      }
-                      _ => {
-                        // Message type not recognized - print the whole message
-                        println!("{}", msg);
-                      }
-                    }
-                  }
-                  "ROOMSTATE" => {
-                    // Room state changed - do nothing? This message is always send with another one?
-                  }
-                  "USERSTATE" => {
-                    if PRINT_CHAT_MESSAGES {
-                      // Bot message
-                      println!("> Bot message from {}", metadata.username);
-                    }
-                  }
-                  _ => {
-                    // Not recognized message
-                    println!("{}", msg);
-                  }
-                }
-              }
+  loop {
+    // Receive
+    match stream.read(&mut buffer) {
+      Ok(0) => return Disconnect::Closed,
+      Ok(len) => {
+        last_received = Instant::now();
+        // data is whole received message, it may contain multiple messages
+        data.push_str(&String::from_utf8_lossy(&buffer[..len])); // lossy conversion is needed because if the data contained a char outside of utf-8 range it will crash the program
 
-              // Move the start index of the message to the end index + 2 characters ("\r\n")
-              msg_start = msg_end + 2;
-            }
-          } else {
-            log::warn!("Chat bot connection was closed due to receiving zero-length data. Waiting some time and reconnecting");
-            let _ = stream.shutdown(std::net::Shutdown::Both);
-            thread::sleep(timeout_error);
-            break;
+        // Loop through every complete message in data, incomplete one is left for the next read
+        while let Some(msg_end) = data.find("\r\n") {
+          let msg: String = data.drain(..(msg_end + 2)).collect();
+          let msg = &msg[..msg_end];
+          if msg.len() == 0 {
+            continue;
           }
-        }
-
-        // Send
-        match last_send_time.elapsed() {
-          Ok(elapsed) => {
-            if elapsed >= SEND_TIMEOUT {
-              last_send_time = SystemTime::now();
 
-              let mut queue = SENDQUEUE.lock().unwrap();
-              if queue.len() > 0 {
-                let msg = queue.pop_front().unwrap();
-                stream
-                  .write(msg.as_bytes())
-                  .expect("Something went wrong when sending the message");
-                drop(msg);
+          match ChatMessage::parse(msg) {
+            Some(ChatMessage::Reconnect) => return Disconnect::Reconnect,
+            Some(ChatMessage::Other(irc)) if irc.command == "001" => {
+              // Welcome message - logged in successfully
+              log::info!("Chat bot logged in");
+              backoff.reset();
+            }
+            Some(ChatMessage::Notice { text, .. })
+              if text.starts_with("Login") || text.starts_with("Improperly formatted auth") =>
+            {
+              return Disconnect::LoginFailed;
+            }
+            Some(message) => {
+              if let Err(err) = handle_message(stream, message) {
+                return Disconnect::Error(err.to_string());
               }
             }
+            None => log::warn!("Chat message not parsed correctly\n{}", msg),
           }
-          Err(err) => {
-            log::error!(
-              "Error when calculating elapsed time since last message: {}",
-              err
-            );
+        }
+      }
+      Err(err) => match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted => {
+          // Server sends PING every ~5 minutes, no data for longer time means the connection is dead
+          if last_received.elapsed() > STALE_TIMEOUT {
+            return Disconnect::Error("no data received from the server".to_string());
           }
         }
+        _ => return Disconnect::Error(err.to_string()),
+      },
+    }
+
+    // Send
+    timers::update();
+    games::update();
+    if let Some(msg) = limiter::pop() {
+      if let Err(err) = stream.write_all(msg.as_bytes()) {
+        log::warn!("Chat message couldn't be sent: {}", msg.trim_end());
+        return Disconnect::Error(err.to_string());
       }
     }
   }
 }
 
-/// Parses provided `msg` returning `header` and `body` part of it and updating it's `metadata`
-fn parse_message<'a>(msg: &'a str, metadata: &mut Metadata) -> (&'a str, &'a str) {
-  metadata.clear();
-
-  let (mut temp, mut temp2): (usize, usize);
-  let (header, body): (&str, &str);
-
-  // Find header <-> body "separator"
-  match msg.find("tmi.twitch.tv") {
-    Some(index) => temp = index,
-    None => {
-      log::warn!("Chat message not parsed correctly\n{}", msg);
-      return ("", "");
+/// Handles single received chat message. Returns an error if the response couldn't be sent.
+fn handle_message(stream: &mut ChatStream, message: ChatMessage) -> io::Result<()> {
+  match message {
+    ChatMessage::Privmsg(msg) => {
+      history::store(&msg);
+      if moderation::check(&msg) {
+        return Ok(());
+      }
+      points::chatted(&msg);
+      emotes::show(&msg);
+      greetings::check(&msg);
+      timers::message_received(&msg.channel);
+      raffle::check_entry(&msg);
+      scripts::handle_event(
+        scripts::Event::Message,
+        &msg.channel,
+        msg.permission(),
+        scripts::message_context(&msg),
+      );
+      if let Some(reward_id) = &msg.custom_reward_id {
+        println!(
+          "> {} redeemed custom reward with ID: {}. {}",
+          msg.display_name, reward_id, msg.text
+        );
+      } else if let Some(bits) = msg.bits {
+        println!(
+          "> {} cheered with {} bits. {}",
+          msg.display_name, bits, msg.text
+        );
+      } else {
+        if PRINT_CHAT_MESSAGES {
+          println!(
+            "{:^3} {:>20}: {}",
+            msg.badge_short(),
+            msg.display_name,
+            msg.text
+          );
+        }
+        check_for_commands(&msg);
+      }
+    }
+    ChatMessage::UserNotice(notice) => {
+      let (user, body) = (&notice.display_name, &notice.text);
+      match notice.msg_id.as_str() {
+        "sub" => {
+          println!("> {} subscribed! {}", user, body);
+        }
+        "resub" => {
+          println!("> {} resubscribed! {}", user, body);
+        }
+        "subgift" => {
+          println!(
+            "> {} gifted sub to {}! {}",
+            user,
+            notice.param("recipient-display-name"),
+            body
+          );
+        }
+        "submysterygift" => {
+          println!("> {} gifted some subs to random viewers! {}", user, body);
+        }
+        "primepaidupgrade" => {
+          println!("> {} converted prime sub to standard sub! {}", user, body);
+        }
+        "giftpaidupgrade" => {
+          println!(
+            "> {} continuing sub gifted by another chatter! {}",
+            user, body
+          );
+        }
+        "communitypayforward" => {
+          println!(
+            "> {} is paying forward sub gifted by another chatter! {}",
+            user, body
+          );
+        }
+        "announcement" => {
+          println!("> {} announced that {}", user, body);
+        }
+        "raid" => {
+          println!("> {} raided the channel! {}", user, body);
+        }
+        "viewermilestone" => {
+          println!(
+            "> {} did something that fired viewer milestone! {}",
+            user, body
+          );
+        }
+        _ => {
+          // Message type not recognized - print the system message
+          println!("> {} {}", notice.system_msg, body);
+        }
+      }
+    }
+    ChatMessage::ClearChat {
+      channel,
+      target_user,
+      target_user_id,
+      ban_duration,
+    } => {
+      match &target_user_id {
+        Some(user_id) => history::mark_user_deleted(&channel, user_id),
+        None => history::mark_channel_deleted(&channel),
+      }
+      match (target_user, ban_duration) {
+        (Some(user), Some(duration)) => {
+          println!("> {} got timed out for {} s!", user, duration);
+        }
+        (Some(user), None) => {
+          println!("> {} got banned!", user);
+        }
+        _ => {
+          println!("> Chat got cleared");
+        }
+      }
+    }
+    ChatMessage::ClearMsg {
+      login,
+      target_msg_id,
+      text,
+      ..
+    } => {
+      history::mark_message_deleted(&target_msg_id);
+      println!("> {} message got deleted: {}", login, text);
+    }
+    ChatMessage::Notice {
+      channel,
+      msg_id,
+      text,
+    } => {
+      match msg_id.as_str() {
+        "emote_only_on" => {
+          println!("> This room is now in emote-only mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              emote_only: Some(true),
+              ..Default::default()
+            },
+          );
+        }
+        "emote_only_off" => {
+          println!("> This room is no longer in emote-only mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              emote_only: Some(false),
+              ..Default::default()
+            },
+          );
+        }
+        "subs_on" => {
+          println!("> This room is now in subscribers-only mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              subs_only: Some(true),
+              ..Default::default()
+            },
+          );
+        }
+        "subs_off" => {
+          println!("> This room is no longer in subscribers-only mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              subs_only: Some(false),
+              ..Default::default()
+            },
+          );
+        }
+        "followers_on" | "followers_on_zero" => {
+          // Followed time is known only from ROOMSTATE message
+          println!("> This room is now in followers-only mode.");
+        }
+        "followers_off" => {
+          println!("> This room is no longer in followers-only mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              followers_only: Some(-1),
+              ..Default::default()
+            },
+          );
+        }
+        "slow_on" => {
+          // Slow mode duration is known only from ROOMSTATE message
+          println!("> This room is now in slow mode.");
+        }
+        "slow_off" => {
+          println!("> This room is no longer in slow mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              slow: Some(0),
+              ..Default::default()
+            },
+          );
+        }
+        "r9k_on" => {
+          println!("> This room is now in unique-chat mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              unique_chat: Some(true),
+              ..Default::default()
+            },
+          );
+        }
+        "r9k_off" => {
+          println!("> This room is no longer in unique-chat mode.");
+          room_state::update(
+            &channel,
+            RoomStateUpdate {
+              unique_chat: Some(false),
+              ..Default::default()
+            },
+          );
+        }
+        _ => {
+          // Notice type not recognized - print the text
+          println!("> {}", text);
+        }
+      }
+    }
+    ChatMessage::RoomState {
+      channel,
+      room_id,
+      emote_only,
+      followers_only,
+      unique_chat,
+      slow,
+      subs_only,
+    } => {
+      room_state::update(
+        &channel,
+        RoomStateUpdate {
+          slow,
+          followers_only,
+          subs_only,
+          emote_only,
+          unique_chat,
+        },
+      );
+      if room_id.len() > 0 {
+        let mut states = CHANNELS.lock().unwrap();
+        match states.iter_mut().find(|s| s.name == channel) {
+          Some(state) => state.room_id = room_id,
+          None => states.push(ChannelState {
+            name: channel,
+            room_id,
+          }),
+        }
+      }
+    }
+    ChatMessage::UserState {
+      channel,
+      display_name,
+      badges,
+    } => {
+      limiter::set_elevated(
+        &channel,
+        Permission::from_badges(&badges) >= Permission::Vip,
+      );
+      if PRINT_CHAT_MESSAGES {
+        // Bot message
+        println!("> Bot message from {}", display_name);
+      }
+    }
+    ChatMessage::GlobalUserState | ChatMessage::Join | ChatMessage::Part => {}
+    ChatMessage::Ping(server) => {
+      stream.write_all(format!("PONG :{}\r\n", server).as_bytes())?;
+    }
+    ChatMessage::Reconnect => {
+      // Handled by the session
+    }
+    ChatMessage::Other(msg) => {
+      // Numeric replies (001, 353, 366, etc.) and CAP acknowledgement are not used
+      if msg.command.parse::<u16>().is_err() && msg.command != "CAP" {
+        println!("> {} {}", msg.command, msg.params.join(" "));
+      }
     }
   }
+  return Ok(());
+}
 
-  // Get message header
-  header = &msg[..temp];
-  temp += 14; // 14 == "tmi.twitch.tv ".len()
-
-  // Get message type
-  temp2 = match msg[temp..].find(' ') {
-    Some(index) => index,
-    None => msg.len() - temp, // Reconnect message - ":tmi.twitch.tv RECONNECT", maybe something more?
-  };
-  metadata.message_type.push_str(&msg[temp..(temp + temp2)]);
-  temp2 += 1 + temp;
-
-  // Get message body
-  body = match msg[temp2..].find(':') {
-    Some(index) => &msg[(index + 1 + temp2)..],
-    None => "", // No message body found
-  };
-
-  // Get header data
-  let header_data: Vec<&str> = header.split(&[';', ' '][..]).collect();
-  for data in header_data {
-    if data.starts_with("id=") {
-      // Message ID
-      metadata.message_id.push_str(&data[3..]); // 3 == "id=".len()
-    } else if data.starts_with("badges=") {
-      // Badge
-      let badge = &data[7..]; // 7 == "badges=".len()
-      if badge.starts_with("broadcaster") {
-        metadata.badge.push_str("STR");
-      } else if badge.starts_with("moderator") {
-        metadata.badge.push_str("MOD");
-      } else if badge.starts_with("subscriber") {
-        metadata.badge.push_str("SUB");
-      } else if badge.starts_with("vip") {
-        metadata.badge.push_str("VIP");
-      }
-    } else if data.starts_with("display-name=") {
-      // Chatter name
-      metadata.username.push_str(&data[13..]); // 13 == "display-name=".len()
-    } else if data.starts_with("user-id=") {
-      // Chatter user ID
-      metadata.user_id.push_str(&data[8..]); // 8 == "user-id=".len()
-    } else if data.starts_with("custom-reward-id=") {
-      // Custom reward ID
-      metadata.custrom_reward_id.push_str(&data[17..]); // 17 == "custom-reward-id=".len()
-    } else if data.starts_with("bits=") {
-      // Bits
-      metadata.bits.push_str(&data[5..]); // 5 == "bits=".len()
-    } else if data.starts_with("@msg-id=") {
-      // msg_id - special message type, being it's own message
-      metadata.msg_id.push_str(&data[8..]); // 8 == "@msg-id=".len()
-    } else if data.starts_with("msg-id=") {
-      // msg_id - special message type, attached to normal message
-      metadata.msg_id.push_str(&data[7..]); // 7 == "msg-id=".len()
+/// Returns names of the channels the chat bot joins, the main channel is the first one
+pub fn channels() -> Vec<String> {
+  let mut channels = vec![secrets::get_data(secrets::Keys::Channel)];
+  for c in secrets::get_data(secrets::Keys::AdditionalChannels).split(',') {
+    let c = c.trim().trim_start_matches('#');
+    if c.len() > 0 && !channels.iter().any(|ch| ch == c) {
+      channels.push(c.to_string());
     }
   }
+  channels.retain(|c| c.len() > 0);
+  return channels;
+}
 
-  return (header, body);
+/// Returns the ID of the channel, None if the bot didn't join it yet
+pub fn room_id(channel: &str) -> Option<String> {
+  let states = CHANNELS.lock().unwrap();
+  return states
+    .iter()
+    .find(|s| s.name == channel)
+    .map(|s| s.room_id.clone());
 }
 
-/// Sends provided message to the chat.
+/// Sends provided message to the main channel chat.
 pub fn send_message(message: &String) {
+  send_message_with_priority(message, Priority::Normal);
+}
+
+/// Sends provided message to the main channel chat with provided send queue priority.
+pub fn send_message_with_priority(message: &String, priority: Priority) {
+  send_message_to(
+    &secrets::get_data(secrets::Keys::Channel),
+    message,
+    priority,
+  );
+}
+
+/// Sends provided message to the chat of provided channel.
+pub fn send_message_to(channel: &str, message: &str, priority: Priority) {
   let mut msg = String::from("PRIVMSG #");
-  msg.push_str(&secrets::get_data(secrets::Keys::Channel));
+  msg.push_str(channel);
   msg.push_str(" :");
   msg.push_str(message);
   msg.push_str("\r\n");
-  SENDQUEUE.lock().unwrap().push_back(msg);
+  limiter::push(channel, msg, priority);
 }
 
-/// Sends provided message to the chat as response to provided message id.
-#[allow(dead_code)]
-pub fn send_message_response(message: &String, message_id: &String) {
+/// Sends provided message to the chat of provided channel as response to provided message id.
+pub fn send_message_response(channel: &str, message: &str, message_id: &str) {
   let mut msg = String::from("@reply-parent-msg-id=");
   msg.push_str(message_id);
   msg.push_str(" PRIVMSG #");
-  msg.push_str(&secrets::get_data(secrets::Keys::Channel));
+  msg.push_str(channel);
   msg.push_str(" :");
   msg.push_str(message);
   msg.push_str("\r\n");
-  SENDQUEUE.lock().unwrap().push_back(msg);
+  limiter::push(channel, msg, Priority::High);
 }
 
-fn check_for_commands(metadata: &Metadata, msg: &str) {
-  match msg {
+/// Responds to the chat message in the channel it was sent in, or with a whisper if the message was whispered.
+pub fn reply(msg: &Privmsg, message: &str) {
+  if msg.whisper {
+    helix::send_whisper(&msg.user_id, message);
+    return;
+  }
+  send_message_response(&msg.channel, message, &msg.message_id);
+}
+
+fn check_for_commands(msg: &Privmsg) {
+  // Twitch appends invisible character to repeated messages, get rid of it
+  let text = msg
+    .text
+    .trim_end_matches(|c: char| c == '\u{E0000}' || c.is_whitespace());
+  if !text.starts_with('!') {
+    return;
+  }
+  let (command, args) = match text.find(' ') {
+    Some(idx) => (&text[..idx], text[(idx + 1)..].trim()),
+    None => (text, ""),
+  };
+  let command = command.to_lowercase();
+
+  match command.as_str() {
+    "!addcom" | "!editcom" | "!delcom" => {
+      commands::handle_management(msg, &command, args);
+    }
+    "!points" | "!give" | "!addpoints" => {
+      points::handle_command(msg, &command, args);
+    }
+    "!scripts" => {
+      scripts::handle_scripts_command(msg, args);
+    }
+    "!template" => {
+      template::handle_command(msg, args);
+    }
+    "!poll" => {
+      poll::handle_command(msg, args);
+    }
+    "!vote" => {
+      poll::handle_vote(msg, args);
+    }
+    "!raffle" => {
+      raffle::handle_command(msg, args);
+    }
+    "!timer" => {
+      timers::handle_command(msg, args);
+    }
+    "!counter" => {
+      counters::handle_command(msg, args);
+    }
+    "!quote" => {
+      quotes::handle_command(msg, args);
+    }
+    "!gamble" | "!duel" | "!accept" | "!heist" | "!games" => {
+      games::handle_command(msg, &command, args);
+    }
+    "!modes" => {
+      room_state::handle_command(msg);
+    }
+    "!walkon" => {
+      greetings::handle_command(msg, args);
+    }
+    "!emote" => {
+      emotes::handle_command(msg, args);
+    }
+    "!tts" => {
+      tts::handle_command(msg, args);
+    }
+    "!so" | "!shoutout" => {
+      shoutout::handle_command(msg, args);
+    }
+    "!timeout" | "!ban" | "!unban" | "!delete" | "!permit" | "!nuke" => {
+      mod_commands::handle_command(msg, &command, args);
+    }
+    "!filter" => {
+      moderation::handle_command(msg, args);
+    }
     "!bot" => {
-      send_message_response(&"The bot is under development, you can check it out at https://github.com/Abev08/twitch_bot_v3".to_string(), &metadata.message_id);
+      if !commands::check_access(msg, "bot", Permission::Everyone, 30, 0) {
+        return;
+      }
+      reply(msg, "The bot is under development, you can check it out at https://github.com/Abev08/twitch_bot_v3");
     }
     // "get system time" => {
-    //   send_message_response(&format!("{:?}", SystemTime::now()), &metadata.message_id);
+    //   reply(msg, &format!("{:?}", SystemTime::now()));
     // }
     // "!example" => {
-    //   send_message_response(&"Example response".to_owned(), &metadata.message_id);
+    //   reply(msg, "Example response");
     // }
-    _ => {}
+    _ => {
+      if scripts::handle_command(msg, &command, args) {
+        return;
+      }
+      if counters::handle_counter_command(msg, &command) {
+        return;
+      }
+      if let Some(response) = commands::get_response(msg, &command) {
+        let ctx = template::Context::from_message(msg, args);
+        if template::uses_helix(&response) {
+          // Twitch API requests would hold up the chat
+          let msg = msg.clone();
+          thread::spawn(move || reply(&msg, &template::render(&response, &ctx)));
+        } else {
+          reply(msg, &template::render(&response, &ctx));
+        }
+      }
+    }
   }
 }
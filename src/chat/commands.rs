@@ -0,0 +1,377 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use crate::{database, scripts};
+
+use super::{counters, reply, Permission, Privmsg};
+
+/// Custom chat command stored in the database
+struct Command {
+  /// Channel the command belongs to, empty for commands available in every channel
+  channel: String,
+  name: String,
+  response: String,
+  /// Minimum permission level required to use the command
+  permission: Permission,
+  /// Time in seconds before the command can be used again by anyone
+  global_cooldown: u32,
+  /// Time in seconds before the command can be used again by the same user
+  user_cooldown: u32,
+}
+
+/// Active cooldown of the command
+struct Cooldown {
+  channel: String,
+  command: String,
+  /// None for global cooldown
+  user_id: Option<String>,
+  until: Instant,
+}
+
+/// Custom commands loaded from the database
+static COMMANDS: Mutex<Vec<Command>> = Mutex::new(Vec::new());
+/// Currently active command cooldowns
+static COOLDOWNS: Mutex<Vec<Cooldown>> = Mutex::new(Vec::new());
+/// Names of the commands handled by the bot itself, custom commands can't use them
+const BUILTIN_COMMANDS: &[&str] = &[
+  "bot",
+  "addcom",
+  "editcom",
+  "delcom",
+  "filter",
+  "quote",
+  "counter",
+  "points",
+  "give",
+  "addpoints",
+  "template",
+  "scripts",
+  "timer",
+  "raffle",
+  "poll",
+  "vote",
+  "timeout",
+  "ban",
+  "unban",
+  "delete",
+  "permit",
+  "nuke",
+  "so",
+  "shoutout",
+  "gamble",
+  "duel",
+  "accept",
+  "heist",
+  "games",
+  "tts",
+  "emote",
+  "walkon",
+  "modes",
+];
+/// Usage of the commands managing custom commands
+const USAGE: &str =
+  "!name [-ul=everyone|sub|vip|mod|broadcaster] [-cd=seconds] [-ucd=seconds] response";
+
+/// Loads custom commands from the database
+pub fn load() {
+  let mut commands = COMMANDS.lock().unwrap();
+  commands.clear();
+  database::query(
+    "SELECT Channel, Name, Response, Permission, GlobalCooldown, UserCooldown FROM Commands;",
+    &[],
+    |row| {
+      commands.push(Command {
+        channel: row.read::<String, _>(0).unwrap_or_default(),
+        name: row.read::<String, _>(1).unwrap_or_default(),
+        response: row.read::<String, _>(2).unwrap_or_default(),
+        permission: Permission::parse(&row.read::<String, _>(3).unwrap_or_default())
+          .unwrap_or(Permission::Everyone),
+        global_cooldown: row.read::<i64, _>(4).unwrap_or_default() as u32,
+        user_cooldown: row.read::<i64, _>(5).unwrap_or_default() as u32,
+      });
+    },
+  );
+  log::info!("Loaded {} custom chat commands", commands.len());
+}
+
+/// Returns the response of custom command with provided name (without '!')
+/// if the author of the message is allowed to use it and the command is not on cooldown.
+pub fn get_response(msg: &Privmsg, name: &str) -> Option<String> {
+  let name = normalize_name(name);
+  let (response, permission, global_cooldown, user_cooldown) = {
+    let commands = COMMANDS.lock().unwrap();
+    let c = find(&commands, &msg.channel, &name)?;
+    (
+      c.response.clone(),
+      c.permission,
+      c.global_cooldown,
+      c.user_cooldown,
+    )
+  };
+
+  if !check_access(msg, &name, permission, global_cooldown, user_cooldown) {
+    return None;
+  }
+  return Some(response);
+}
+
+/// Checks if the author of the message can use the command.
+/// Returns true if the permission level is high enough and the command is not on cooldown, starting the cooldowns.
+/// Broadcaster and moderators are not affected by cooldowns.
+pub fn check_access(
+  msg: &Privmsg,
+  name: &str,
+  permission: Permission,
+  global_cooldown: u32,
+  user_cooldown: u32,
+) -> bool {
+  let user_permission = msg.permission();
+  if user_permission < permission {
+    return false;
+  }
+  if user_permission >= Permission::Moderator {
+    return true;
+  }
+
+  let now = Instant::now();
+  let mut cooldowns = COOLDOWNS.lock().unwrap();
+  cooldowns.retain(|c| c.until > now);
+  for c in cooldowns.iter() {
+    if c.channel == msg.channel
+      && c.command == name
+      && (c.user_id.is_none() || c.user_id.as_ref() == Some(&msg.user_id))
+    {
+      return false;
+    }
+  }
+
+  if global_cooldown > 0 {
+    cooldowns.push(Cooldown {
+      channel: msg.channel.clone(),
+      command: name.to_string(),
+      user_id: None,
+      until: now + Duration::from_secs(global_cooldown as u64),
+    });
+  }
+  if user_cooldown > 0 {
+    cooldowns.push(Cooldown {
+      channel: msg.channel.clone(),
+      command: name.to_string(),
+      user_id: Some(msg.user_id.clone()),
+      until: now + Duration::from_secs(user_cooldown as u64),
+    });
+  }
+  return true;
+}
+
+/// Returns true if provided command name is handled by the bot itself
+pub fn is_builtin(name: &str) -> bool {
+  return BUILTIN_COMMANDS.contains(&normalize_name(name).as_str());
+}
+
+/// Returns true if the custom command is available in the channel
+pub fn exists(channel: &str, name: &str) -> bool {
+  let commands = COMMANDS.lock().unwrap();
+  return find(&commands, channel, &normalize_name(name)).is_some();
+}
+
+/// Finds the command available in the channel, commands specific to the channel have priority over common ones
+fn find<'a>(commands: &'a [Command], channel: &str, name: &str) -> Option<&'a Command> {
+  return commands
+    .iter()
+    .find(|c| c.name == name && c.channel == channel)
+    .or_else(|| {
+      commands
+        .iter()
+        .find(|c| c.name == name && c.channel.len() == 0)
+    });
+}
+
+/// Handles "!addcom", "!editcom" and "!delcom" commands.
+/// New commands are added to the channel the message was sent in.
+pub fn handle_management(msg: &Privmsg, command: &str, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+
+  let mut words = args.split_whitespace().peekable();
+  let name = normalize_name(words.next().unwrap_or_default());
+  if name.len() == 0 {
+    reply(msg, &format!("Usage: {} {}", command, USAGE));
+    return;
+  }
+  if is_builtin(&name) {
+    reply(
+      msg,
+      &format!("!{} is a built-in command and can't be changed", name),
+    );
+    return;
+  }
+
+  // Options at the beginning of the response
+  let (mut permission, mut global_cooldown, mut user_cooldown) = (None, None, None);
+  while let Some(word) = words.peek() {
+    if let Some(value) = word.strip_prefix("-ul=") {
+      permission = Permission::parse(value);
+      if permission.is_none() {
+        reply(msg, &format!("Unknown user level '{}'", value));
+        return;
+      }
+    } else if let Some(value) = word.strip_prefix("-cd=") {
+      global_cooldown = value.parse::<u32>().ok();
+      if global_cooldown.is_none() {
+        reply(msg, &format!("Usage: {} {}", command, USAGE));
+        return;
+      }
+    } else if let Some(value) = word.strip_prefix("-ucd=") {
+      user_cooldown = value.parse::<u32>().ok();
+      if user_cooldown.is_none() {
+        reply(msg, &format!("Usage: {} {}", command, USAGE));
+        return;
+      }
+    } else {
+      break;
+    }
+    words.next();
+  }
+  let response = words.collect::<Vec<&str>>().join(" ");
+  let existing = {
+    let commands = COMMANDS.lock().unwrap();
+    find(&commands, &msg.channel, &name).map(|c| Command {
+      channel: c.channel.clone(),
+      name: c.name.clone(),
+      response: c.response.clone(),
+      permission: c.permission,
+      global_cooldown: c.global_cooldown,
+      user_cooldown: c.user_cooldown,
+    })
+  };
+
+  let answer = match command {
+    "!addcom" => {
+      if response.len() == 0 {
+        format!("Usage: {} {}", command, USAGE)
+      } else if existing.is_some() {
+        format!(
+          "Command !{} already exists, use !editcom to change it",
+          name
+        )
+      } else if counters::exists(&msg.channel, name.trim_end_matches(['+', '-'])) {
+        format!("!{} is already used by a counter", name)
+      } else if scripts::is_command(&name) {
+        format!("!{} is already used by a script", name)
+      } else if save(
+        Command {
+          channel: msg.channel.clone(),
+          name: name.clone(),
+          response,
+          permission: permission.unwrap_or(Permission::Everyone),
+          global_cooldown: global_cooldown.unwrap_or(0),
+          user_cooldown: user_cooldown.unwrap_or(0),
+        },
+        false,
+      ) {
+        format!("Command !{} added", name)
+      } else {
+        format!("Couldn't add command !{}", name)
+      }
+    }
+    "!editcom" => match existing {
+      None => format!("Command !{} doesn't exist", name),
+      Some(c) => {
+        if response.len() == 0
+          && permission.is_none()
+          && global_cooldown.is_none()
+          && user_cooldown.is_none()
+        {
+          format!("Usage: {} {}", command, USAGE)
+        } else if save(
+          Command {
+            channel: c.channel,
+            name: name.clone(),
+            response: if response.len() > 0 {
+              response
+            } else {
+              c.response
+            },
+            permission: permission.unwrap_or(c.permission),
+            global_cooldown: global_cooldown.unwrap_or(c.global_cooldown),
+            user_cooldown: user_cooldown.unwrap_or(c.user_cooldown),
+          },
+          true,
+        ) {
+          format!("Command !{} updated", name)
+        } else {
+          format!("Couldn't update command !{}", name)
+        }
+      }
+    },
+    "!delcom" => match existing {
+      None => format!("Command !{} doesn't exist", name),
+      Some(c) => {
+        if delete(&c.channel, &name) {
+          format!("Command !{} deleted", name)
+        } else {
+          format!("Couldn't delete command !{}", name)
+        }
+      }
+    },
+    _ => return,
+  };
+  reply(msg, &answer);
+}
+
+/// Adds new or updates existing command in the database. Returns true on success.
+fn save(command: Command, update: bool) -> bool {
+  let query = if update {
+    "UPDATE Commands SET Response = ?, Permission = ?, GlobalCooldown = ?, UserCooldown = ? WHERE Channel = ? AND Name = ?;"
+  } else {
+    "INSERT INTO Commands (Response, Permission, GlobalCooldown, UserCooldown, Channel, Name) VALUES (?, ?, ?, ?, ?, ?);"
+  };
+  if !database::execute(
+    query,
+    &[
+      command.response.as_str().into(),
+      format!("{:?}", command.permission).into(),
+      (command.global_cooldown as i64).into(),
+      (command.user_cooldown as i64).into(),
+      command.channel.as_str().into(),
+      command.name.as_str().into(),
+    ],
+  ) {
+    return false;
+  }
+
+  log::info!(
+    "{} custom command !{}",
+    if update { "Updated" } else { "Added" },
+    command.name
+  );
+  let mut commands = COMMANDS.lock().unwrap();
+  commands.retain(|c| c.channel != command.channel || c.name != command.name);
+  commands.push(command);
+  return true;
+}
+
+/// Deletes the command from the database. Returns true on success.
+fn delete(channel: &str, name: &str) -> bool {
+  if !database::execute(
+    "DELETE FROM Commands WHERE Channel = ? AND Name = ?;",
+    &[channel.into(), name.into()],
+  ) {
+    return false;
+  }
+  COMMANDS
+    .lock()
+    .unwrap()
+    .retain(|c| c.channel != channel || c.name != name);
+  log::info!("Deleted custom command !{}", name);
+  return true;
+}
+
+/// Command names are stored lowercase without the '!' prefix
+fn normalize_name(name: &str) -> String {
+  return name.trim().trim_start_matches('!').to_lowercase();
+}
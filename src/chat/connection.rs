@@ -0,0 +1,132 @@
+use std::{
+  io::{self, Read, Write},
+  net::{Shutdown, TcpStream},
+  time::Duration,
+};
+
+use native_tls::{TlsConnector, TlsStream};
+use rand::Rng;
+
+use crate::secrets;
+
+/// Twitch IRC server address, TLS connection
+const TWITCH_SERVER: (&str, u16) = ("irc.chat.twitch.tv", 6697);
+/// Read timeout used during TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Reconnect delay after first failed attempt, doubled with every next failed attempt
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// Maximum reconnect delay
+const BACKOFF_MAX: Duration = Duration::from_secs(120);
+
+/// Jittered exponential backoff of reconnect attempts
+pub struct Backoff {
+  failed_attempts: u32,
+}
+
+impl Backoff {
+  pub fn new() -> Self {
+    return Self { failed_attempts: 0 };
+  }
+
+  /// Returns the delay before next connection attempt, None if it should be done immediately.
+  /// The delay is randomized between half and full exponential delay so multiple clients don't reconnect at once.
+  pub fn next_delay(&self) -> Option<Duration> {
+    if self.failed_attempts == 0 {
+      return None;
+    }
+    let exp = BACKOFF_BASE
+      .saturating_mul(1 << (self.failed_attempts - 1).min(16))
+      .min(BACKOFF_MAX);
+    let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+    return Some(exp.mul_f64(jitter));
+  }
+
+  /// Marks connection attempt as failed, increasing next delay
+  pub fn failed(&mut self) {
+    self.failed_attempts = self.failed_attempts.saturating_add(1);
+  }
+
+  /// Resets the delay after successful connection
+  pub fn reset(&mut self) {
+    self.failed_attempts = 0;
+  }
+}
+
+/// Connection to the chat server
+pub enum ChatStream {
+  /// Unencrypted connection, only for local test servers
+  Plain(TcpStream),
+  Tls(TlsStream<TcpStream>),
+}
+
+impl ChatStream {
+  /// Connects to Twitch IRC server using TLS.
+  /// If `ChatPlaintextServer` is set in secrets.ini, connects to that server without encryption instead.
+  pub fn connect(read_timeout: Duration) -> Result<Self, String> {
+    let plaintext_server = secrets::get_data(secrets::Keys::ChatPlaintextServer);
+    if plaintext_server.len() > 0 {
+      log::warn!(
+        "Chat bot connecting to {} without encryption",
+        plaintext_server
+      );
+      let stream = TcpStream::connect(&plaintext_server).map_err(|e| e.to_string())?;
+      stream
+        .set_read_timeout(Some(read_timeout))
+        .map_err(|e| e.to_string())?;
+      return Ok(ChatStream::Plain(stream));
+    }
+
+    let (host, port) = TWITCH_SERVER;
+    let stream = TcpStream::connect((host, port)).map_err(|e| e.to_string())?;
+    stream
+      .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+      .map_err(|e| e.to_string())?;
+    let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+    let stream = connector
+      .connect(host, stream)
+      .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    stream
+      .get_ref()
+      .set_read_timeout(Some(read_timeout))
+      .map_err(|e| e.to_string())?;
+    return Ok(ChatStream::Tls(stream));
+  }
+
+  /// Closes the connection
+  pub fn shutdown(&mut self) {
+    match self {
+      ChatStream::Plain(stream) => {
+        let _ = stream.shutdown(Shutdown::Both);
+      }
+      ChatStream::Tls(stream) => {
+        let _ = stream.shutdown();
+        let _ = stream.get_ref().shutdown(Shutdown::Both);
+      }
+    }
+  }
+}
+
+impl Read for ChatStream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      ChatStream::Plain(stream) => stream.read(buf),
+      ChatStream::Tls(stream) => stream.read(buf),
+    }
+  }
+}
+
+impl Write for ChatStream {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      ChatStream::Plain(stream) => stream.write(buf),
+      ChatStream::Tls(stream) => stream.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      ChatStream::Plain(stream) => stream.flush(),
+      ChatStream::Tls(stream) => stream.flush(),
+    }
+  }
+}
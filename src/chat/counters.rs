@@ -0,0 +1,210 @@
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::{client, database, scripts};
+
+use super::{commands, reply, Permission, Privmsg};
+
+/// Named counter, like deaths or wins
+struct Counter {
+  channel: String,
+  name: String,
+  value: i64,
+}
+
+/// Counters loaded from the database
+static COUNTERS: Mutex<Vec<Counter>> = Mutex::new(Vec::new());
+/// Usage of the command managing counters
+const USAGE: &str = "!counter add <name>, !counter delete <name>, !counter set <name> <value>, then !name, !name+, !name-";
+/// Counter values are kept between -MAX_VALUE and MAX_VALUE
+const MAX_VALUE: i64 = 1_000_000_000_000;
+
+/// Loads counters from the database and sends them to the overlay
+pub fn load() {
+  let mut counters = COUNTERS.lock().unwrap();
+  counters.clear();
+  database::query("SELECT Channel, Name, Value FROM Counters;", &[], |row| {
+    counters.push(Counter {
+      channel: row.read::<String, _>(0).unwrap_or_default(),
+      name: row.read::<String, _>(1).unwrap_or_default(),
+      value: row.read::<i64, _>(2).unwrap_or_default(),
+    });
+  });
+  for c in counters.iter() {
+    update_widget(c, false);
+  }
+}
+
+/// Returns the value of the counter, None if it doesn't exist
+pub fn get(channel: &str, name: &str) -> Option<i64> {
+  let counters = COUNTERS.lock().unwrap();
+  return counters
+    .iter()
+    .find(|c| c.channel == channel && c.name == name)
+    .map(|c| c.value);
+}
+
+/// Returns true if the counter exists in the channel
+pub fn exists(channel: &str, name: &str) -> bool {
+  return get(channel, name).is_some();
+}
+
+/// Sets the value of the counter, creating it if it doesn't exist
+pub fn set(channel: &str, name: &str, value: i64) {
+  let mut counters = COUNTERS.lock().unwrap();
+  let index = match counters
+    .iter()
+    .position(|c| c.channel == channel && c.name == name)
+  {
+    Some(i) => i,
+    None => {
+      counters.push(Counter {
+        channel: channel.to_string(),
+        name: name.to_string(),
+        value: 0,
+      });
+      counters.len() - 1
+    }
+  };
+  counters[index].value = value.clamp(-MAX_VALUE, MAX_VALUE);
+  save(&counters[index]);
+  update_widget(&counters[index], false);
+}
+
+/// Handles "!name", "!name+" and "!name-" commands of existing counters.
+/// Returns false if the command doesn't belong to any counter.
+pub fn handle_counter_command(msg: &Privmsg, command: &str) -> bool {
+  let name = command.trim_start_matches('!');
+  let (name, change) = if let Some(name) = name.strip_suffix('+') {
+    (name, 1)
+  } else if let Some(name) = name.strip_suffix('-') {
+    (name, -1)
+  } else {
+    (name, 0)
+  };
+
+  let value = {
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = match counters
+      .iter_mut()
+      .find(|c| c.channel == msg.channel && c.name == name)
+    {
+      Some(c) => c,
+      None => return false,
+    };
+    if msg.permission() < Permission::Moderator {
+      return true;
+    }
+    if change != 0 {
+      counter.value = counter
+        .value
+        .saturating_add(change)
+        .clamp(-MAX_VALUE, MAX_VALUE);
+      save(counter);
+      update_widget(counter, false);
+    }
+    counter.value
+  };
+  reply(msg, &format!("{}: {}", name, value));
+  return true;
+}
+
+/// Handles "!counter" command creating, deleting and setting counters
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let action = words.first().map(|w| w.to_lowercase()).unwrap_or_default();
+  let name = words
+    .get(1)
+    .map(|w| w.trim_start_matches('!').to_lowercase())
+    .unwrap_or_default();
+  if name.len() == 0 {
+    reply(msg, &format!("Usage: {}", USAGE));
+    return;
+  }
+
+  let mut counters = COUNTERS.lock().unwrap();
+  let index = counters
+    .iter()
+    .position(|c| c.channel == msg.channel && c.name == name);
+  let answer = match (action.as_str(), index) {
+    ("add", Some(_)) => format!("Counter {} already exists", name),
+    ("add", None) => {
+      if commands::is_builtin(&name) || name.ends_with(['+', '-']) {
+        format!("{} can't be used as a counter name", name)
+      } else if commands::exists(&msg.channel, &name) {
+        format!("!{} is already used by a custom command", name)
+      } else if scripts::is_command(&name) {
+        format!("!{} is already used by a script", name)
+      } else {
+        let counter = Counter {
+          channel: msg.channel.clone(),
+          name: name.clone(),
+          value: 0,
+        };
+        save(&counter);
+        update_widget(&counter, false);
+        counters.push(counter);
+        format!(
+          "Counter {} added, use !{}+ and !{}- to change it",
+          name, name, name
+        )
+      }
+    }
+    ("delete" | "del" | "remove", Some(i)) => {
+      let counter = counters.remove(i);
+      database::execute(
+        "DELETE FROM Counters WHERE Channel = ? AND Name = ?;",
+        &[
+          counter.channel.as_str().into(),
+          counter.name.as_str().into(),
+        ],
+      );
+      update_widget(&counter, true);
+      format!("Counter {} deleted", name)
+    }
+    ("set", Some(i)) => match words.get(2).and_then(|v| v.parse::<i64>().ok()) {
+      Some(value) => {
+        counters[i].value = value.clamp(-MAX_VALUE, MAX_VALUE);
+        save(&counters[i]);
+        update_widget(&counters[i], false);
+        format!("{}: {}", name, counters[i].value)
+      }
+      None => format!("Usage: {}", USAGE),
+    },
+    ("delete" | "del" | "remove" | "set", None) => format!("Counter {} doesn't exist", name),
+    _ => format!("Usage: {}", USAGE),
+  };
+  drop(counters);
+  reply(msg, &answer);
+}
+
+/// Stores the counter in the database
+fn save(counter: &Counter) {
+  database::execute(
+    "INSERT INTO Counters (Channel, Name, Value) VALUES (?, ?, ?) ON CONFLICT(Channel, Name) DO UPDATE SET Value = excluded.Value;",
+    &[
+      counter.channel.as_str().into(),
+      counter.name.as_str().into(),
+      counter.value.into(),
+    ],
+  );
+}
+
+/// Sends current value of the counter to the overlay counter widget
+fn update_widget(counter: &Counter, removed: bool) {
+  client::send_widget_message(
+    &format!("counter {} {}", counter.channel, counter.name),
+    &json!({
+      "widget": "counter",
+      "channel": counter.channel,
+      "name": counter.name,
+      "value": counter.value,
+      "removed": removed,
+    })
+    .to_string(),
+  );
+}
@@ -0,0 +1,211 @@
+use std::{fs, sync::Mutex};
+
+use serde_json::json;
+
+use crate::{client, database};
+
+use super::{reply, Permission, Privmsg};
+
+/// Emote used in the chat message
+#[derive(Clone, Debug)]
+pub struct Emote {
+  /// Twitch emote ID, name of the emote for local emotes
+  pub id: String,
+  pub name: String,
+  /// Index of the first character of the emote in the message text
+  pub start: usize,
+  /// Index of the last character of the emote in the message text
+  pub end: usize,
+  /// Is the emote from the local emote set?
+  pub local: bool,
+}
+
+impl Emote {
+  /// Address the overlay can load the image of the emote from
+  pub fn url(&self) -> String {
+    if self.local {
+      return format!("emote?name={}", client::url_encode(&self.name));
+    }
+    return format!(
+      "https://static-cdn.jtvnw.net/emoticons/v2/{}/default/dark/2.0",
+      self.id
+    );
+  }
+}
+
+/// Local emotes (name, image file name in the emotes directory)
+static LOCAL: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+/// The last emote combo of every channel (channel, emote name, count)
+static COMBOS: Mutex<Vec<(String, String, u32)>> = Mutex::new(Vec::new());
+/// Directory the images of local emotes are loaded from
+pub const EMOTES_DIR: &str = "emotes";
+/// Number of messages with the same emote in a row that is shown as a combo
+const COMBO_MIN: u32 = 3;
+/// Usage of the command managing local emotes
+const USAGE: &str = "!emote add <name> <file in emotes directory>, !emote delete <name>";
+
+/// Loads local emotes from the database
+pub fn load() {
+  let mut local = LOCAL.lock().unwrap();
+  local.clear();
+  database::query("SELECT Name, File FROM Emotes;", &[], |row| {
+    local.push((
+      row.read::<String, _>(0).unwrap_or_default(),
+      row.read::<String, _>(1).unwrap_or_default(),
+    ));
+  });
+}
+
+/// Parses "emotes" tag value ("25:0-4,12-16/1902:6-10") and finds local emotes in the text.
+/// Returned emotes are sorted by their position in the text.
+pub fn parse(value: Option<&str>, text: &str) -> Vec<Emote> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut emotes = Vec::new();
+  for emote in value.unwrap_or_default().split('/') {
+    let (id, ranges) = match emote.split_once(':') {
+      Some(e) => e,
+      None => continue,
+    };
+    for range in ranges.split(',') {
+      let (start, end) = match range
+        .split_once('-')
+        .and_then(|(s, e)| Some((s.parse::<usize>().ok()?, e.parse::<usize>().ok()?)))
+      {
+        Some(r) if r.0 <= r.1 && r.1 < chars.len() => r,
+        _ => continue,
+      };
+      emotes.push(Emote {
+        id: id.to_string(),
+        name: chars[start..=end].iter().collect(),
+        start,
+        end,
+        local: false,
+      });
+    }
+  }
+
+  let local = LOCAL.lock().unwrap();
+  if local.len() > 0 {
+    let mut start = 0;
+    for word in text.split(' ') {
+      let length = word.chars().count();
+      if length > 0
+        && local.iter().any(|(name, _)| name == word)
+        && !emotes.iter().any(|e| e.start == start)
+      {
+        emotes.push(Emote {
+          id: word.to_string(),
+          name: word.to_string(),
+          start,
+          end: start + length - 1,
+          local: true,
+        });
+      }
+      start += length + 1;
+    }
+  }
+  emotes.sort_by_key(|e| e.start);
+  return emotes;
+}
+
+/// Returns the image of the local emote
+pub fn get_image(name: &str) -> Option<Vec<u8>> {
+  let file = {
+    let local = LOCAL.lock().unwrap();
+    local.iter().find(|(n, _)| n == name)?.1.clone()
+  };
+  match fs::read(format!("{}/{}", EMOTES_DIR, file)) {
+    Ok(data) => return Some(data),
+    Err(err) => {
+      log::warn!("Couldn't read emote {} image {}. {}", name, file, err);
+      return None;
+    }
+  }
+}
+
+/// Sends the message with its emotes to the overlay (chat, emote wall and emote combos)
+pub fn show(msg: &Privmsg) {
+  let combo = {
+    let single = match msg.emotes.first() {
+      Some(first) if msg.emotes.iter().all(|e| e.name == first.name) => Some(&first.name),
+      _ => None,
+    };
+    let mut combos = COMBOS.lock().unwrap();
+    let index = match combos.iter().position(|c| c.0 == msg.channel) {
+      Some(i) => i,
+      None => {
+        combos.push((msg.channel.clone(), String::new(), 0));
+        combos.len() - 1
+      }
+    };
+    let combo = &mut combos[index];
+    match single {
+      Some(name) if *name == combo.1 => combo.2 += 1,
+      Some(name) => *combo = (msg.channel.clone(), name.clone(), 1),
+      None => *combo = (msg.channel.clone(), String::new(), 0),
+    }
+    if combo.2 >= COMBO_MIN {
+      combo.2
+    } else {
+      0
+    }
+  };
+
+  let emotes: Vec<serde_json::Value> = msg
+    .emotes
+    .iter()
+    .map(|e| json!({ "name": e.name, "start": e.start, "end": e.end, "url": e.url() }))
+    .collect();
+  client::send_event_message(
+    &json!({
+      "widget": "chat",
+      "channel": msg.channel,
+      "user": msg.display_name,
+      "text": msg.text,
+      "emotes": emotes,
+      "combo": combo,
+    })
+    .to_string(),
+  );
+}
+
+/// Handles "!emote" command managing local emotes
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let answer = match (words.first().copied(), words.get(1), words.get(2)) {
+    (Some("add"), Some(name), Some(file)) => {
+      if file.contains("..") || file.contains('/') || file.contains('\\') {
+        "The file has to be in the emotes directory".to_string()
+      } else if !database::execute(
+        "INSERT INTO Emotes (Name, File) VALUES (?, ?) ON CONFLICT(Name) DO UPDATE SET File = excluded.File;",
+        &[(*name).into(), (*file).into()],
+      ) {
+        format!("Couldn't add emote {}", name)
+      } else {
+        load();
+        format!("Emote {} added", name)
+      }
+    }
+    (Some("delete" | "del" | "remove"), Some(name), _) => {
+      database::execute("DELETE FROM Emotes WHERE Name = ?;", &[(*name).into()]);
+      load();
+      format!("Emote {} deleted", name)
+    }
+    _ => {
+      let names: Vec<String> = LOCAL.lock().unwrap().iter().map(|e| e.0.clone()).collect();
+      format!(
+        "Local emotes: {}. Usage: {}",
+        if names.len() > 0 {
+          names.join(" ")
+        } else {
+          "none".to_string()
+        },
+        USAGE
+      )
+    }
+  };
+  reply(msg, &answer);
+}
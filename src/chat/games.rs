@@ -0,0 +1,485 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{database, points};
+
+use super::{check_access, reply, send_message_to, Permission, Priority, Privmsg};
+
+/// Settings of the game in the channel
+struct Settings {
+  /// Chance of winning in percent
+  chance: u32,
+  /// Cooldown in seconds, per user for gamble and duel, per channel for heist
+  cooldown: u32,
+}
+
+/// Duel waiting for the target to accept it, the bet of the challenger is already taken
+struct Duel {
+  channel: String,
+  challenger_id: String,
+  challenger: String,
+  target_id: String,
+  target: String,
+  amount: i64,
+  expires: Instant,
+}
+
+/// Heist collecting participants (user ID, display name, bet)
+struct Heist {
+  channel: String,
+  participants: Vec<(String, String, i64)>,
+  ends: Instant,
+}
+
+/// Duels waiting for acceptance
+static DUELS: Mutex<Vec<Duel>> = Mutex::new(Vec::new());
+/// Heists collecting participants
+static HEISTS: Mutex<Vec<Heist>> = Mutex::new(Vec::new());
+/// Time the last heist of the channel ended
+static HEIST_ENDS: Mutex<Vec<(String, Instant)>> = Mutex::new(Vec::new());
+/// Names of the games with default chance and cooldown
+const GAMES: &[(&str, u32, u32)] = &[("gamble", 45, 30), ("duel", 50, 60), ("heist", 60, 600)];
+/// Time the target of the duel has to accept it
+const DUEL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time the users can join the heist after it started
+const HEIST_JOIN_WINDOW: Duration = Duration::from_secs(60);
+/// Payout multipliers of the heist survivors by the number of participants (minimum participants, multiplier)
+const HEIST_TIERS: &[(usize, f64)] = &[(1, 1.5), (5, 2.0), (10, 2.5), (20, 3.0)];
+/// Usage of the command changing game settings
+const USAGE: &str = "!games <gamble|duel|heist> [-chance=percent] [-cd=seconds]";
+
+/// Refunds the bets of duels and heists that were still waiting when the bot stopped
+pub fn load() {
+  let mut bets: Vec<(String, i64)> = Vec::new();
+  database::query("SELECT UserID, Amount FROM GameBets;", &[], |row| {
+    bets.push((
+      row.read::<String, _>(0).unwrap_or_default(),
+      row.read::<i64, _>(1).unwrap_or_default(),
+    ));
+  });
+  if bets.len() == 0 {
+    return;
+  }
+  for (user_id, amount) in bets.iter() {
+    points::add(user_id, *amount);
+  }
+  database::execute("DELETE FROM GameBets;", &[]);
+  log::info!("Refunded {} unfinished game bets", bets.len());
+}
+
+/// Handles "!gamble", "!duel", "!accept", "!heist" and "!games" commands
+pub fn handle_command(msg: &Privmsg, command: &str, args: &str) {
+  match command {
+    "!gamble" => gamble(msg, args),
+    "!duel" => duel(msg, args),
+    "!accept" => accept(msg),
+    "!heist" => heist(msg, args),
+    "!games" => settings_command(msg, args),
+    _ => {}
+  }
+}
+
+/// Resolves duels that weren't accepted and heists with finished join window
+pub fn update() {
+  let now = Instant::now();
+  let expired: Vec<Duel> = {
+    let mut duels = DUELS.lock().unwrap();
+    let (expired, waiting) = duels.drain(..).partition(|d| d.expires <= now);
+    *duels = waiting;
+    expired
+  };
+  for d in expired {
+    points::add(&d.challenger_id, d.amount);
+    remove_bet(&d.channel, "duel", &d.challenger_id);
+    send_message_to(
+      &d.channel,
+      &format!(
+        "{} didn't accept the duel, {} got the points back",
+        d.target, d.challenger
+      ),
+      Priority::Normal,
+    );
+  }
+
+  let finished: Vec<Heist> = {
+    let mut heists = HEISTS.lock().unwrap();
+    let (finished, joining) = heists.drain(..).partition(|h| h.ends <= now);
+    *heists = joining;
+    finished
+  };
+  for h in finished {
+    resolve_heist(h);
+  }
+}
+
+/// "!gamble <amount|all>", the bet is doubled on win
+fn gamble(msg: &Privmsg, args: &str) {
+  let settings = settings(&msg.channel, "gamble");
+  let amount = match parse_amount(&msg.user_id, args) {
+    Some(a) => a,
+    None => {
+      reply(msg, "Usage: !gamble <amount|all>");
+      return;
+    }
+  };
+  if !has_points(msg, amount)
+    || !check_access(msg, "gamble", Permission::Everyone, 0, settings.cooldown)
+  {
+    return;
+  }
+  if !points::spend(&msg.user_id, amount) {
+    reply(msg, "You don't have enough points");
+    return;
+  }
+  let text = if roll(settings.chance) {
+    points::add(&msg.user_id, amount * 2);
+    format!(
+      "{} gambled {} points and won! They now have {} points",
+      msg.display_name,
+      amount,
+      points::get(&msg.user_id)
+    )
+  } else {
+    format!(
+      "{} gambled {} points and lost them all. They now have {} points",
+      msg.display_name,
+      amount,
+      points::get(&msg.user_id)
+    )
+  };
+  send_message_to(&msg.channel, &text, Priority::Normal);
+}
+
+/// "!duel <user> <amount>", the target has to accept the duel with "!accept"
+fn duel(msg: &Privmsg, args: &str) {
+  let settings = settings(&msg.channel, "duel");
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let (target, amount) = match (
+    words.first().and_then(|u| points::find_viewer(u)),
+    words.get(1).and_then(|a| parse_amount(&msg.user_id, a)),
+  ) {
+    (Some(t), Some(a)) => (t, a),
+    (None, _) if words.len() > 0 => {
+      reply(
+        msg,
+        &format!(
+          "{} doesn't have any points yet",
+          words[0].trim_start_matches('@')
+        ),
+      );
+      return;
+    }
+    _ => {
+      reply(msg, "Usage: !duel <user> <amount>");
+      return;
+    }
+  };
+  if target.user_id == msg.user_id {
+    return;
+  }
+
+  let mut duels = DUELS.lock().unwrap();
+  if duels.iter().any(|d| {
+    d.channel == msg.channel && (d.challenger_id == msg.user_id || d.target_id == target.user_id)
+  }) {
+    drop(duels);
+    reply(msg, "There is already a duel waiting");
+    return;
+  }
+  if !has_points(msg, amount)
+    || !check_access(msg, "duel", Permission::Everyone, 0, settings.cooldown)
+  {
+    return;
+  }
+  if !points::spend(&msg.user_id, amount) {
+    drop(duels);
+    reply(msg, "You don't have enough points");
+    return;
+  }
+  duels.push(Duel {
+    channel: msg.channel.clone(),
+    challenger_id: msg.user_id.clone(),
+    challenger: msg.display_name.clone(),
+    target_id: target.user_id.clone(),
+    target: target.display_name.clone(),
+    amount,
+    expires: Instant::now() + DUEL_TIMEOUT,
+  });
+  drop(duels);
+  store_bet(&msg.channel, "duel", &msg.user_id, amount);
+  send_message_to(
+    &msg.channel,
+    &format!(
+      "@{}, {} challenged you to a duel for {} points! Type !accept within {} seconds",
+      target.display_name,
+      msg.display_name,
+      amount,
+      DUEL_TIMEOUT.as_secs()
+    ),
+    Priority::Normal,
+  );
+}
+
+/// "!accept" resolving the duel the user was challenged to
+fn accept(msg: &Privmsg) {
+  let duel = {
+    let mut duels = DUELS.lock().unwrap();
+    match duels
+      .iter()
+      .position(|d| d.channel == msg.channel && d.target_id == msg.user_id)
+    {
+      Some(i) => duels.remove(i),
+      None => return,
+    }
+  };
+  remove_bet(&duel.channel, "duel", &duel.challenger_id);
+  if !points::spend(&msg.user_id, duel.amount) {
+    points::add(&duel.challenger_id, duel.amount);
+    reply(
+      msg,
+      &format!(
+        "You don't have {} points, the duel is cancelled",
+        duel.amount
+      ),
+    );
+    return;
+  }
+  let chance = settings(&msg.channel, "duel").chance;
+  let (winner_id, winner, loser) = if roll(chance) {
+    (&duel.challenger_id, &duel.challenger, &duel.target)
+  } else {
+    (&duel.target_id, &duel.target, &duel.challenger)
+  };
+  points::add(winner_id, duel.amount * 2);
+  send_message_to(
+    &msg.channel,
+    &format!(
+      "{} won the duel against {} and takes {} points!",
+      winner, loser, duel.amount
+    ),
+    Priority::Normal,
+  );
+}
+
+/// "!heist <amount|all>" starting the heist or joining the one collecting participants
+fn heist(msg: &Privmsg, args: &str) {
+  let settings = settings(&msg.channel, "heist");
+  let amount = match parse_amount(&msg.user_id, args) {
+    Some(a) => a,
+    None => {
+      reply(msg, "Usage: !heist <amount|all>");
+      return;
+    }
+  };
+
+  let mut heists = HEISTS.lock().unwrap();
+  let index = heists.iter().position(|h| h.channel == msg.channel);
+  if let Some(i) = index {
+    if heists[i].participants.iter().any(|p| p.0 == msg.user_id) {
+      return;
+    }
+  } else {
+    let ends = HEIST_ENDS.lock().unwrap();
+    let cooldown = Duration::from_secs(settings.cooldown as u64);
+    if let Some((_, time)) = ends.iter().find(|(c, _)| *c == msg.channel) {
+      if time.elapsed() < cooldown {
+        let left = (cooldown - time.elapsed()).as_secs() / 60 + 1;
+        drop(ends);
+        drop(heists);
+        reply(
+          msg,
+          &format!("The crew is still hiding, next heist in {} min", left),
+        );
+        return;
+      }
+    }
+  }
+  if !points::spend(&msg.user_id, amount) {
+    drop(heists);
+    reply(msg, "You don't have enough points");
+    return;
+  }
+  store_bet(&msg.channel, "heist", &msg.user_id, amount);
+  let participant = (msg.user_id.clone(), msg.display_name.clone(), amount);
+  match index {
+    Some(i) => heists[i].participants.push(participant),
+    None => {
+      heists.push(Heist {
+        channel: msg.channel.clone(),
+        participants: vec![participant],
+        ends: Instant::now() + HEIST_JOIN_WINDOW,
+      });
+      drop(heists);
+      send_message_to(
+        &msg.channel,
+        &format!(
+          "{} is planning a heist! Join with !heist <amount> within {} seconds",
+          msg.display_name,
+          HEIST_JOIN_WINDOW.as_secs()
+        ),
+        Priority::Normal,
+      );
+    }
+  }
+}
+
+/// Every participant of the heist survives with the chance of the game,
+/// survivors get their bet multiplied by the tier of the crew size
+fn resolve_heist(heist: Heist) {
+  let settings = settings(&heist.channel, "heist");
+  let multiplier = HEIST_TIERS
+    .iter()
+    .rev()
+    .find(|(size, _)| heist.participants.len() >= *size)
+    .map(|(_, m)| *m)
+    .unwrap_or(1.0);
+  database::execute(
+    "DELETE FROM GameBets WHERE Channel = ? AND Game = 'heist';",
+    &[heist.channel.as_str().into()],
+  );
+  let mut survivors = Vec::new();
+  for (user_id, name, amount) in heist.participants.iter() {
+    if roll(settings.chance) {
+      let payout = (*amount as f64 * multiplier) as i64;
+      points::add(user_id, payout);
+      survivors.push(format!("{} ({})", name, payout));
+    }
+  }
+  {
+    let mut ends = HEIST_ENDS.lock().unwrap();
+    ends.retain(|(c, _)| *c != heist.channel);
+    ends.push((heist.channel.clone(), Instant::now()));
+  }
+
+  let text = if survivors.len() == 0 {
+    format!(
+      "The heist failed, all {} members of the crew got caught!",
+      heist.participants.len()
+    )
+  } else {
+    format!(
+      "The heist is over! {} of {} made it out: {}",
+      survivors.len(),
+      heist.participants.len(),
+      survivors.join(", ")
+    )
+  };
+  log::info!("Heist in #{}: {}", heist.channel, text);
+  send_message_to(&heist.channel, &text, Priority::Normal);
+}
+
+/// "!games" showing or changing the settings of the game
+fn settings_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let mut words = args.split_whitespace();
+  let name = words.next().unwrap_or_default().to_lowercase();
+  if !GAMES.iter().any(|(n, _, _)| *n == name) {
+    reply(msg, &format!("Usage: {}", USAGE));
+    return;
+  }
+  let mut settings = settings(&msg.channel, &name);
+  let mut changed = false;
+  for word in words {
+    if let Some(chance) = word
+      .strip_prefix("-chance=")
+      .and_then(|c| c.parse::<u32>().ok())
+    {
+      settings.chance = chance.min(100);
+      changed = true;
+    } else if let Some(cooldown) = word
+      .strip_prefix("-cd=")
+      .and_then(|c| c.parse::<u32>().ok())
+    {
+      settings.cooldown = cooldown;
+      changed = true;
+    }
+  }
+  if changed {
+    database::execute(
+      "INSERT INTO Games (Channel, Name, Chance, Cooldown) VALUES (?, ?, ?, ?) \
+      ON CONFLICT(Channel, Name) DO UPDATE SET Chance = excluded.Chance, Cooldown = excluded.Cooldown;",
+      &[
+        msg.channel.as_str().into(),
+        name.as_str().into(),
+        (settings.chance as i64).into(),
+        (settings.cooldown as i64).into(),
+      ],
+    );
+  }
+  reply(
+    msg,
+    &format!(
+      "{}: {}% chance to win, {} s cooldown",
+      name, settings.chance, settings.cooldown
+    ),
+  );
+}
+
+/// Returns settings of the game in the channel, defaults if they weren't changed
+fn settings(channel: &str, name: &str) -> Settings {
+  let (_, chance, cooldown) = GAMES
+    .iter()
+    .find(|(n, _, _)| *n == name)
+    .copied()
+    .unwrap_or_default();
+  let mut settings = Settings { chance, cooldown };
+  database::query(
+    "SELECT Chance, Cooldown FROM Games WHERE Channel = ? AND Name = ?;",
+    &[channel.into(), name.into()],
+    |row| {
+      settings.chance = row.read::<i64, _>(0).unwrap_or_default() as u32;
+      settings.cooldown = row.read::<i64, _>(1).unwrap_or_default() as u32;
+    },
+  );
+  return settings;
+}
+
+/// Returns true if the user has at least `amount` points, tells them if they don't
+fn has_points(msg: &Privmsg, amount: i64) -> bool {
+  if points::get(&msg.user_id) >= amount {
+    return true;
+  }
+  reply(msg, "You don't have enough points");
+  return false;
+}
+
+/// Stores the bet taken from the user, so it can be refunded if the bot stops before the game ends
+fn store_bet(channel: &str, game: &str, user_id: &str, amount: i64) {
+  database::execute(
+    "INSERT INTO GameBets (Channel, Game, UserID, Amount) VALUES (?, ?, ?, ?);",
+    &[channel.into(), game.into(), user_id.into(), amount.into()],
+  );
+}
+
+/// Removes the stored bet when the game ends
+fn remove_bet(channel: &str, game: &str, user_id: &str) {
+  database::execute(
+    "DELETE FROM GameBets WHERE Channel = ? AND Game = ? AND UserID = ?;",
+    &[channel.into(), game.into(), user_id.into()],
+  );
+}
+
+/// Parses positive amount of points or "all" points of the user
+fn parse_amount(user_id: &str, text: &str) -> Option<i64> {
+  let text = text.trim();
+  let amount = if text.eq_ignore_ascii_case("all") {
+    points::get(user_id)
+  } else {
+    text.parse::<i64>().ok()?
+  };
+  if amount <= 0 {
+    return None;
+  }
+  return Some(amount);
+}
+
+/// Returns true with provided chance in percent
+fn roll(chance: u32) -> bool {
+  return rand::thread_rng().gen_range(0..100) < chance;
+}
@@ -0,0 +1,159 @@
+use std::{sync::Mutex, thread};
+
+use crate::{database, notifications, points, secrets, template};
+
+use super::{reply, Permission, Privmsg};
+
+/// Users that already chatted during current stream (channel, user ID)
+static SEEN: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+/// Directory the walk-on sounds are loaded from
+pub const WALKONS_DIR: &str = "walkons";
+/// Usage of the command managing walk-on sounds
+const USAGE: &str = "!walkon <user> <file in walkons directory> [volume 0-100], !walkon <user> off";
+
+/// Greets the author of the message if it's their first message in the channel or first message of the stream.
+/// Greeting texts are templates "FirstChatterChat", "FirstChatterDisplayed", "ReturningChatterChat" and
+/// "ReturningChatterDisplayed", template set to "off" disables the greeting. Walk-on sound is played in both cases.
+pub fn check(msg: &Privmsg) {
+  if msg.channel != secrets::get_data(secrets::Keys::Channel)
+    || msg.permission() == Permission::Broadcaster
+  {
+    return;
+  }
+  {
+    let mut seen = SEEN.lock().unwrap();
+    if seen
+      .iter()
+      .any(|(c, id)| *c == msg.channel && *id == msg.user_id)
+    {
+      return;
+    }
+    seen.push((msg.channel.clone(), msg.user_id.clone()));
+  }
+
+  let first_time = msg.tags.get("first-msg").map(|f| f == "1").unwrap_or(false);
+  let (chat, displayed) = if first_time {
+    log::info!("{} chatted for the first time", msg.display_name);
+    (
+      template::get("FirstChatterChat", "Welcome to the chat @$(user)!"),
+      template::get(
+        "FirstChatterDisplayed",
+        "$(user) is here for the first time!",
+      ),
+    )
+  } else {
+    (
+      template::get("ReturningChatterChat", "off"),
+      template::get("ReturningChatterDisplayed", "off"),
+    )
+  };
+  let ctx = template::Context::from_message(msg, "");
+  let walkon = walkon(&msg.user_id);
+  if template::uses_helix(&chat) || template::uses_helix(&displayed) {
+    // Twitch API requests would hold up the chat
+    thread::spawn(move || greet(chat, displayed, walkon, &ctx));
+  } else {
+    greet(chat, displayed, walkon, &ctx);
+  }
+}
+
+/// Renders the greeting texts and adds the greeting notification
+fn greet(chat: String, displayed: String, walkon: Option<(String, f32)>, ctx: &template::Context) {
+  let render = |text: String| match text.as_str() {
+    "off" => None,
+    _ => Some(template::render(&text, ctx)),
+  };
+  let chat = render(chat);
+  let displayed = render(displayed);
+  if chat.is_none() && displayed.is_none() && walkon.is_none() {
+    return;
+  }
+  notifications::add_greeting_notification(chat, displayed, walkon);
+}
+
+/// Forgets who already chatted, called when the stream goes live
+pub fn stream_started(channel: &str) {
+  SEEN.lock().unwrap().retain(|(c, _)| c != channel);
+}
+
+/// Returns the walk-on sound path the overlay can load and its volume
+fn walkon(user_id: &str) -> Option<(String, f32)> {
+  let mut walkon = None;
+  database::query(
+    "SELECT Volume FROM WalkOns WHERE UserID = ?;",
+    &[user_id.into()],
+    |row| {
+      walkon = Some((
+        format!("walkon?id={}", user_id),
+        row.read::<f64, _>(0).unwrap_or(1.0) as f32,
+      ))
+    },
+  );
+  return walkon;
+}
+
+/// Returns the path of the walk-on sound file of the user
+pub fn walkon_file(user_id: &str) -> Option<String> {
+  let mut file = None;
+  database::query(
+    "SELECT File FROM WalkOns WHERE UserID = ?;",
+    &[user_id.into()],
+    |row| {
+      file = Some(format!(
+        "{}/{}",
+        WALKONS_DIR,
+        row.read::<String, _>(0).unwrap_or_default()
+      ))
+    },
+  );
+  return file;
+}
+
+/// Handles "!walkon" command managing walk-on sounds of the users
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let (user, file) = match (words.first(), words.get(1)) {
+    (Some(user), Some(file)) => (user.trim_start_matches('@'), *file),
+    _ => {
+      reply(msg, &format!("Usage: {}", USAGE));
+      return;
+    }
+  };
+  let viewer = match points::find_viewer(user) {
+    Some(v) => v,
+    None => {
+      reply(msg, &format!("{} didn't chat yet", user));
+      return;
+    }
+  };
+
+  let answer = if file == "off" {
+    database::execute(
+      "DELETE FROM WalkOns WHERE UserID = ?;",
+      &[viewer.user_id.as_str().into()],
+    );
+    format!("Walk-on sound of {} removed", viewer.display_name)
+  } else if file.contains("..") || file.contains('/') || file.contains('\\') {
+    "The file has to be in the walkons directory".to_string()
+  } else {
+    let volume = words
+      .get(2)
+      .and_then(|v| v.parse::<u32>().ok())
+      .unwrap_or(100)
+      .min(100);
+    database::execute(
+      "INSERT INTO WalkOns (UserID, File, Volume) VALUES (?, ?, ?) \
+      ON CONFLICT(UserID) DO UPDATE SET File = excluded.File, Volume = excluded.Volume;",
+      &[
+        viewer.user_id.as_str().into(),
+        file.into(),
+        (volume as f64 / 100.0).into(),
+      ],
+    );
+    format!("Walk-on sound of {} set to {}", viewer.display_name, file)
+  };
+  reply(msg, &answer);
+}
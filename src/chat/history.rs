@@ -0,0 +1,191 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, TimeZone};
+use serde_json::json;
+use sqlite::Value;
+
+use crate::database;
+
+use super::Privmsg;
+
+/// Chat message stored in the database
+pub struct HistoryEntry {
+  pub message_id: String,
+  pub channel: String,
+  pub user_id: String,
+  pub login: String,
+  pub display_name: String,
+  pub badges: String,
+  pub text: String,
+  pub timestamp: DateTime<Local>,
+  /// Was the message deleted by moderators, or the user got timed out / banned?
+  pub deleted: bool,
+}
+
+impl HistoryEntry {
+  pub fn to_json(&self) -> serde_json::Value {
+    return json!({
+      "message_id": self.message_id,
+      "channel": self.channel,
+      "user_id": self.user_id,
+      "login": self.login,
+      "display_name": self.display_name,
+      "badges": self.badges,
+      "text": self.text,
+      "timestamp": self.timestamp.to_rfc3339(),
+      "deleted": self.deleted,
+    });
+  }
+}
+
+/// Search criteria of the chat history, not set fields are not checked
+#[derive(Default)]
+pub struct HistoryFilter {
+  pub channel: Option<String>,
+  /// Login or display name of the user, case insensitive
+  pub user: Option<String>,
+  /// Text contained in the message, case insensitive
+  pub text: Option<String>,
+  pub from: Option<DateTime<Local>>,
+  pub to: Option<DateTime<Local>>,
+  /// Maximum number of returned messages, the newest ones are returned
+  pub limit: Option<u32>,
+}
+
+/// Default maximum number of messages returned by the search
+const DEFAULT_LIMIT: u32 = 500;
+/// How long the messages are kept in the history
+const RETENTION: chrono::Duration = chrono::Duration::days(30);
+/// How often the messages older than `RETENTION` are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// When were the old messages last removed
+static LAST_PRUNE: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Stores chat message in the database
+pub fn store(msg: &Privmsg) {
+  let timestamp = msg
+    .tags
+    .get("tmi-sent-ts")
+    .and_then(|t| t.parse::<i64>().ok())
+    .unwrap_or_else(|| Local::now().timestamp_millis());
+  database::execute(
+    "INSERT INTO ChatHistory (MessageID, Channel, UserID, Login, DisplayName, Badges, Text, Timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
+    &[
+      msg.message_id.as_str().into(),
+      msg.channel.as_str().into(),
+      msg.user_id.as_str().into(),
+      msg.login.as_str().into(),
+      msg.display_name.as_str().into(),
+      msg.tags.get("badges").map(|b| b.as_str()).unwrap_or_default().into(),
+      msg.text.as_str().into(),
+      timestamp.into(),
+    ],
+  );
+  prune();
+}
+
+/// Removes messages older than `RETENTION`, at most once per `PRUNE_INTERVAL`
+fn prune() {
+  {
+    let mut last = LAST_PRUNE.lock().unwrap();
+    if last.is_some_and(|l| l.elapsed() < PRUNE_INTERVAL) {
+      return;
+    }
+    *last = Some(Instant::now());
+  }
+  if let Some(removed) = database::execute_count(
+    "DELETE FROM ChatHistory WHERE Timestamp < ?;",
+    &[(Local::now() - RETENTION).timestamp_millis().into()],
+  ) {
+    if removed > 0 {
+      log::info!("Removed {} old messages from chat history", removed);
+    }
+  }
+}
+
+/// Marks single message as deleted (CLEARMSG)
+pub fn mark_message_deleted(message_id: &str) {
+  database::execute(
+    "UPDATE ChatHistory SET Deleted = 1 WHERE MessageID = ?;",
+    &[message_id.into()],
+  );
+}
+
+/// Marks all messages of the user in the channel as deleted (CLEARCHAT for timeout / ban)
+pub fn mark_user_deleted(channel: &str, user_id: &str) {
+  database::execute(
+    "UPDATE ChatHistory SET Deleted = 1 WHERE Channel = ? AND UserID = ? AND Timestamp <= ?;",
+    &[
+      channel.into(),
+      user_id.into(),
+      Local::now().timestamp_millis().into(),
+    ],
+  );
+}
+
+/// Marks all messages in the channel as deleted (CLEARCHAT for whole chat)
+pub fn mark_channel_deleted(channel: &str) {
+  database::execute(
+    "UPDATE ChatHistory SET Deleted = 1 WHERE Channel = ? AND Timestamp <= ?;",
+    &[channel.into(), Local::now().timestamp_millis().into()],
+  );
+}
+
+/// Returns chat messages matching the filter, sorted from the oldest one
+pub fn search(filter: &HistoryFilter) -> Vec<HistoryEntry> {
+  let mut query = String::from(
+    "SELECT MessageID, Channel, UserID, Login, DisplayName, Badges, Text, Timestamp, Deleted FROM ChatHistory WHERE 1 = 1",
+  );
+  let mut params: Vec<Value> = Vec::new();
+  if let Some(channel) = &filter.channel {
+    query.push_str(" AND Channel = ?");
+    params.push(channel.to_lowercase().trim_start_matches('#').into());
+  }
+  if let Some(user) = &filter.user {
+    query.push_str(" AND (Login = ? OR DisplayName = ? COLLATE NOCASE)");
+    let user = user.trim_start_matches('@').to_lowercase();
+    params.push(user.as_str().into());
+    params.push(user.as_str().into());
+  }
+  if let Some(text) = &filter.text {
+    query.push_str(" AND Text LIKE ? ESCAPE '\\'");
+    let escaped = text
+      .replace('\\', "\\\\")
+      .replace('%', "\\%")
+      .replace('_', "\\_");
+    params.push(format!("%{}%", escaped).into());
+  }
+  if let Some(from) = &filter.from {
+    query.push_str(" AND Timestamp >= ?");
+    params.push(from.timestamp_millis().into());
+  }
+  if let Some(to) = &filter.to {
+    query.push_str(" AND Timestamp <= ?");
+    params.push(to.timestamp_millis().into());
+  }
+  query.push_str(" ORDER BY Timestamp DESC LIMIT ?;");
+  params.push((filter.limit.unwrap_or(DEFAULT_LIMIT) as i64).into());
+
+  let mut entries = Vec::new();
+  database::query(&query, &params, |row| {
+    entries.push(HistoryEntry {
+      message_id: row.read::<String, _>(0).unwrap_or_default(),
+      channel: row.read::<String, _>(1).unwrap_or_default(),
+      user_id: row.read::<String, _>(2).unwrap_or_default(),
+      login: row.read::<String, _>(3).unwrap_or_default(),
+      display_name: row.read::<String, _>(4).unwrap_or_default(),
+      badges: row.read::<String, _>(5).unwrap_or_default(),
+      text: row.read::<String, _>(6).unwrap_or_default(),
+      timestamp: Local
+        .timestamp_millis_opt(row.read::<i64, _>(7).unwrap_or_default())
+        .single()
+        .unwrap_or_default(),
+      deleted: row.read::<i64, _>(8).unwrap_or_default() != 0,
+    });
+  });
+  entries.reverse();
+  return entries;
+}
@@ -0,0 +1,296 @@
+use std::{
+  collections::VecDeque,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// Priority of the message waiting in the send queue, higher priority messages are sent first
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Priority {
+  /// Periodic messages, can wait
+  Low,
+  /// Notifications
+  Normal,
+  /// Responses to commands
+  High,
+}
+
+struct QueuedMessage {
+  channel: String,
+  priority: Priority,
+  data: String,
+  queued: Instant,
+}
+
+/// Sliding window rate limiter of sent messages, every channel has it's own window.
+/// https://dev.twitch.tv/docs/irc/#rate-limits
+struct RateLimiter {
+  /// Send times of the messages in current window of every channel
+  sent: Vec<(String, VecDeque<Instant>)>,
+  /// Send time of the last message in any channel
+  last: Option<Instant>,
+  /// Channels in which the bot is a moderator, VIP or the broadcaster, allows higher message rate in them
+  elevated: Vec<String>,
+  /// Was the backlog warning already printed?
+  backlog_warned: bool,
+}
+
+/// Queue for messages that should be send
+static QUEUE: Mutex<VecDeque<QueuedMessage>> = Mutex::new(VecDeque::new());
+static LIMITER: Mutex<RateLimiter> = Mutex::new(RateLimiter::new());
+/// Length of the rate limit window
+const WINDOW: Duration = Duration::from_secs(30);
+/// Maximum number of messages sent in the window by regular user
+const LIMIT: usize = 20;
+/// Maximum number of messages sent in the window by moderator, VIP or the broadcaster
+const LIMIT_ELEVATED: usize = 100;
+/// Minimum time between sending messages
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+/// Queue length above which the backlog warning is printed
+const BACKLOG_WARNING: usize = 10;
+/// Messages waiting longer are sent like high priority ones, so they can't be delayed forever
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Adds the message (whole IRC line) to the send queue
+pub fn push(channel: &str, data: String, priority: Priority) {
+  QUEUE.lock().unwrap().push_back(QueuedMessage {
+    channel: channel.to_string(),
+    priority,
+    data,
+    queued: Instant::now(),
+  });
+}
+
+/// Returns next message that can be sent without exceeding the rate limit and marks it as sent.
+/// Returns None if the queue is empty or the limit is reached.
+pub fn pop() -> Option<String> {
+  let mut queue = QUEUE.lock().unwrap();
+  let mut limiter = LIMITER.lock().unwrap();
+  return limiter.pop(&mut queue, Instant::now());
+}
+
+impl RateLimiter {
+  const fn new() -> Self {
+    return Self {
+      sent: Vec::new(),
+      last: None,
+      elevated: Vec::new(),
+      backlog_warned: false,
+    };
+  }
+
+  /// Removes the first message with the highest priority whose channel is not over the limit from the queue
+  fn pop(&mut self, queue: &mut VecDeque<QueuedMessage>, now: Instant) -> Option<String> {
+    if queue.len() > BACKLOG_WARNING && !self.backlog_warned {
+      self.backlog_warned = true;
+      log::warn!(
+        "Chat send queue backlog: {} messages waiting, oldest for {} s",
+        queue.len(),
+        queue
+          .iter()
+          .map(|m| now - m.queued)
+          .max()
+          .unwrap_or_default()
+          .as_secs()
+      );
+    } else if queue.len() == 0 {
+      self.backlog_warned = false;
+    }
+
+    for (_, sent) in self.sent.iter_mut() {
+      while let Some(first) = sent.front() {
+        if now - *first >= WINDOW {
+          sent.pop_front();
+        } else {
+          break;
+        }
+      }
+    }
+    self.sent.retain(|(_, sent)| sent.len() > 0);
+    if queue.len() == 0 {
+      return None;
+    }
+    if let Some(last) = self.last {
+      if now - last < MIN_INTERVAL {
+        return None;
+      }
+    }
+
+    let mut index: Option<usize> = None;
+    let mut best = Priority::Low;
+    for (i, queued) in queue.iter().enumerate() {
+      if self.is_limited(&queued.channel) {
+        continue;
+      }
+      let priority = if now - queued.queued >= MAX_WAIT {
+        Priority::High
+      } else {
+        queued.priority
+      };
+      if index.is_none() || priority > best {
+        index = Some(i);
+        best = priority;
+      }
+    }
+    let msg = queue.remove(index?)?;
+    match self.sent.iter_mut().find(|(c, _)| *c == msg.channel) {
+      Some((_, sent)) => sent.push_back(now),
+      None => self.sent.push((msg.channel, VecDeque::from([now]))),
+    }
+    self.last = Some(now);
+    return Some(msg.data);
+  }
+
+  /// Returns true if the channel reached the limit of sent messages in current window
+  fn is_limited(&self, channel: &str) -> bool {
+    let limit = if self.elevated.iter().any(|c| c == channel) {
+      LIMIT_ELEVATED
+    } else {
+      LIMIT
+    };
+    return self
+      .sent
+      .iter()
+      .find(|(c, _)| c == channel)
+      .map(|(_, sent)| sent.len() >= limit)
+      .unwrap_or(false);
+  }
+}
+
+/// Updates the rate limit of the channel based on the bot badges in it
+pub fn set_elevated(channel: &str, elevated: bool) {
+  let mut limiter = LIMITER.lock().unwrap();
+  let index = limiter.elevated.iter().position(|c| c == channel);
+  if index.is_some() == elevated {
+    return;
+  }
+  match index {
+    Some(i) => {
+      limiter.elevated.remove(i);
+    }
+    None => limiter.elevated.push(channel.to_string()),
+  }
+  log::info!(
+    "Chat send rate limit in #{} set to {} messages per {} s",
+    channel,
+    if elevated { LIMIT_ELEVATED } else { LIMIT },
+    WINDOW.as_secs()
+  );
+}
+
+/// Returns the number of messages waiting in the send queue and the time the oldest one is waiting
+pub fn backlog() -> (usize, Duration) {
+  let now = Instant::now();
+  let queue = QUEUE.lock().unwrap();
+  return (
+    queue.len(),
+    queue
+      .iter()
+      .map(|m| now - m.queued)
+      .max()
+      .unwrap_or_default(),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn queued(channel: &str, data: &str, priority: Priority, queued: Instant) -> QueuedMessage {
+    return QueuedMessage {
+      channel: channel.to_string(),
+      priority,
+      data: data.to_string(),
+      queued,
+    };
+  }
+
+  #[test]
+  fn pops_highest_priority_first() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new();
+    let mut queue = VecDeque::from([
+      queued("a", "low", Priority::Low, now),
+      queued("a", "high", Priority::High, now),
+      queued("a", "normal", Priority::Normal, now),
+      queued("a", "high 2", Priority::High, now),
+    ]);
+    let mut time = now;
+    let mut sent = Vec::new();
+    while let Some(data) = limiter.pop(&mut queue, time) {
+      sent.push(data);
+      time += MIN_INTERVAL;
+    }
+    assert_eq!(sent, vec!["high", "high 2", "normal", "low"]);
+  }
+
+  #[test]
+  fn keeps_minimum_interval() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new();
+    let mut queue = VecDeque::from([
+      queued("a", "1", Priority::Normal, now),
+      queued("b", "2", Priority::Normal, now),
+    ]);
+    assert_eq!(limiter.pop(&mut queue, now).as_deref(), Some("1"));
+    assert_eq!(limiter.pop(&mut queue, now + MIN_INTERVAL / 2), None);
+    assert_eq!(
+      limiter.pop(&mut queue, now + MIN_INTERVAL).as_deref(),
+      Some("2")
+    );
+    assert_eq!(limiter.pop(&mut queue, now + MIN_INTERVAL * 2), None);
+  }
+
+  #[test]
+  fn limits_every_channel_separately() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new();
+    let mut queue = VecDeque::new();
+    for _ in 0..(LIMIT + 1) {
+      queue.push_back(queued("a", "a", Priority::High, now));
+    }
+    queue.push_back(queued("b", "b", Priority::Low, now));
+
+    let mut time = now;
+    for _ in 0..LIMIT {
+      assert_eq!(limiter.pop(&mut queue, time).as_deref(), Some("a"));
+      time += MIN_INTERVAL;
+    }
+    // Channel "a" is over the limit, but "b" can still send
+    assert_eq!(limiter.pop(&mut queue, time).as_deref(), Some("b"));
+    time += MIN_INTERVAL;
+    assert_eq!(limiter.pop(&mut queue, time), None);
+    // The window of "a" moved
+    assert_eq!(limiter.pop(&mut queue, now + WINDOW).as_deref(), Some("a"));
+  }
+
+  #[test]
+  fn elevated_channel_has_higher_limit() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new();
+    limiter.elevated.push("a".to_string());
+    let mut queue = VecDeque::new();
+    for _ in 0..(LIMIT + 1) {
+      queue.push_back(queued("a", "a", Priority::Normal, now));
+    }
+    let mut time = now;
+    for _ in 0..(LIMIT + 1) {
+      assert_eq!(limiter.pop(&mut queue, time).as_deref(), Some("a"));
+      time += MIN_INTERVAL;
+    }
+  }
+
+  #[test]
+  fn old_low_priority_message_is_not_starved() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new();
+    let mut queue = VecDeque::from([
+      queued("a", "old", Priority::Low, now),
+      queued("a", "new", Priority::High, now + MAX_WAIT),
+    ]);
+    assert_eq!(
+      limiter.pop(&mut queue, now + MAX_WAIT).as_deref(),
+      Some("old")
+    );
+  }
+}
@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+
+use super::emotes::{self, Emote};
+
+/// Raw IRC message split into it's parts.
+/// https://ircv3.net/specs/extensions/message-tags
+pub struct IrcMessage {
+  pub tags: HashMap<String, String>,
+  pub prefix: Option<String>,
+  pub command: String,
+  pub params: Vec<String>,
+}
+
+impl IrcMessage {
+  /// Parses single IRC message line (without "\r\n").
+  /// Returns None if the line doesn't contain a command.
+  pub fn parse(line: &str) -> Option<Self> {
+    let mut rest = line.trim_start();
+    let mut tags = HashMap::new();
+    let mut prefix = None;
+
+    // Tags - "@key=value;key2=value2 "
+    if let Some(stripped) = rest.strip_prefix('@') {
+      let (raw_tags, remaining) = split_word(stripped);
+      for tag in raw_tags.split(';') {
+        if tag.len() == 0 {
+          continue;
+        }
+        match tag.find('=') {
+          Some(idx) => tags.insert(
+            tag[..idx].to_string(),
+            unescape_tag_value(&tag[(idx + 1)..]),
+          ),
+          None => tags.insert(tag.to_string(), String::new()),
+        };
+      }
+      rest = remaining;
+    }
+
+    // Prefix - ":nick!user@host "
+    if let Some(stripped) = rest.strip_prefix(':') {
+      let (raw_prefix, remaining) = split_word(stripped);
+      prefix = Some(raw_prefix.to_string());
+      rest = remaining;
+    }
+
+    // Command
+    let (command, remaining) = split_word(rest);
+    if command.len() == 0 {
+      return None;
+    }
+    rest = remaining;
+
+    // Parameters, the last one may be "trailing" one starting with ':' and containing spaces
+    let mut params = Vec::new();
+    while rest.len() > 0 {
+      if let Some(trailing) = rest.strip_prefix(':') {
+        params.push(trailing.to_string());
+        break;
+      }
+      let (param, remaining) = split_word(rest);
+      params.push(param.to_string());
+      rest = remaining;
+    }
+
+    return Some(Self {
+      tags,
+      prefix,
+      command: command.to_string(),
+      params,
+    });
+  }
+
+  /// Returns the value of the tag, None if the tag is missing or it's value is empty.
+  pub fn tag(&self, key: &str) -> Option<&str> {
+    match self.tags.get(key) {
+      Some(value) if value.len() > 0 => Some(value.as_str()),
+      _ => None,
+    }
+  }
+
+  /// Returns the value of the tag or empty string.
+  pub fn tag_or_empty(&self, key: &str) -> String {
+    return self.tag(key).unwrap_or_default().to_string();
+  }
+
+  /// Returns the parameter at provided index or empty string.
+  pub fn param(&self, index: usize) -> &str {
+    return self
+      .params
+      .get(index)
+      .map(|p| p.as_str())
+      .unwrap_or_default();
+  }
+
+  /// Returns nickname part of the prefix ("nick" from ":nick!user@host").
+  pub fn nick(&self) -> &str {
+    match &self.prefix {
+      Some(prefix) => match prefix.find('!') {
+        Some(idx) => &prefix[..idx],
+        None => prefix,
+      },
+      None => "",
+    }
+  }
+}
+
+/// Splits provided text at first space, skipping any following spaces.
+fn split_word(text: &str) -> (&str, &str) {
+  match text.find(' ') {
+    Some(idx) => (&text[..idx], text[idx..].trim_start_matches(' ')),
+    None => (text, ""),
+  }
+}
+
+/// Reverts IRCv3 tag value escaping.
+fn unescape_tag_value(value: &str) -> String {
+  let mut ret = String::with_capacity(value.len());
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      ret.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some(':') => ret.push(';'),
+      Some('s') => ret.push(' '),
+      Some('\\') => ret.push('\\'),
+      Some('r') => ret.push('\r'),
+      Some('n') => ret.push('\n'),
+      Some(other) => ret.push(other),
+      None => {} // Trailing backslash is dropped
+    }
+  }
+  return ret;
+}
+
+/// Chat badge, for example "subscriber" from "subscriber/12".
+#[derive(Clone, Debug)]
+pub struct Badge {
+  pub name: String,
+}
+
+/// Permission level of the chatter, ordered from the lowest to the highest.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Permission {
+  Everyone,
+  Subscriber,
+  Vip,
+  Moderator,
+  Broadcaster,
+}
+
+impl Permission {
+  /// Returns permission level of the chatter with provided badges.
+  pub fn from_badges(badges: &[Badge]) -> Self {
+    let has = |name: &str| badges.iter().any(|b| b.name == name);
+    if has("broadcaster") {
+      return Permission::Broadcaster;
+    } else if has("moderator") {
+      return Permission::Moderator;
+    } else if has("vip") {
+      return Permission::Vip;
+    } else if has("subscriber") || has("founder") {
+      return Permission::Subscriber;
+    }
+    return Permission::Everyone;
+  }
+
+  /// Parses permission level name, accepts short forms like "sub" or "mod".
+  pub fn parse(name: &str) -> Option<Self> {
+    match name.to_lowercase().as_str() {
+      "everyone" | "all" => Some(Permission::Everyone),
+      "subscriber" | "sub" => Some(Permission::Subscriber),
+      "vip" => Some(Permission::Vip),
+      "moderator" | "mod" => Some(Permission::Moderator),
+      "broadcaster" | "streamer" | "owner" => Some(Permission::Broadcaster),
+      _ => None,
+    }
+  }
+}
+
+/// Parses "badges" tag value ("broadcaster/1,subscriber/0").
+pub fn parse_badges(value: Option<&str>) -> Vec<Badge> {
+  let mut badges = Vec::new();
+  if let Some(value) = value {
+    for badge in value.split(',') {
+      let name = badge.split('/').next().unwrap_or_default();
+      if name.len() > 0 {
+        badges.push(Badge {
+          name: name.to_string(),
+        });
+      }
+    }
+  }
+  return badges;
+}
+
+/// Strips '#' from the channel parameter.
+fn channel_name(param: &str) -> String {
+  return param.trim_start_matches('#').to_string();
+}
+
+/// Chat message sent by the user.
+#[derive(Clone)]
+pub struct Privmsg {
+  pub channel: String,
+  /// ID of the channel owner
+  pub room_id: String,
+  pub message_id: String,
+  pub user_id: String,
+  pub login: String,
+  pub display_name: String,
+  pub badges: Vec<Badge>,
+  pub text: String,
+  /// Twitch and local emotes used in the text
+  pub emotes: Vec<Emote>,
+  pub bits: Option<u32>,
+  pub custom_reward_id: Option<String>,
+  pub tags: HashMap<String, String>,
+  /// Was the message received as a whisper? Responses to it are whispered back.
+  pub whisper: bool,
+}
+
+impl Privmsg {
+  /// Permission level of the message author.
+  pub fn permission(&self) -> Permission {
+    return Permission::from_badges(&self.badges);
+  }
+
+  /// Short, three letter representation of the most important badge of the message author.
+  pub fn badge_short(&self) -> &'static str {
+    match self.permission() {
+      Permission::Broadcaster => "STR",
+      Permission::Moderator => "MOD",
+      Permission::Vip => "VIP",
+      Permission::Subscriber => "SUB",
+      Permission::Everyone => "",
+    }
+  }
+
+  /// Number of emotes used in the message, including local emotes.
+  pub fn emote_count(&self) -> usize {
+    return self.emotes.len();
+  }
+}
+
+/// Chat notification like subscription, raid or announcement.
+pub struct UserNotice {
+  pub msg_id: String,
+  pub display_name: String,
+  pub system_msg: String,
+  /// Optional message attached by the user
+  pub text: String,
+  pub tags: HashMap<String, String>,
+}
+
+impl UserNotice {
+  /// Returns the value of "msg-param-`name`" tag or empty string.
+  pub fn param(&self, name: &str) -> &str {
+    return self
+      .tags
+      .get(&format!("msg-param-{}", name))
+      .map(|p| p.as_str())
+      .unwrap_or_default();
+  }
+}
+
+/// Typed chat message.
+/// https://dev.twitch.tv/docs/irc/commands/
+pub enum ChatMessage {
+  Privmsg(Privmsg),
+  UserNotice(UserNotice),
+  /// All messages in the channel or messages of a single user got cleared (ban / timeout)
+  ClearChat {
+    channel: String,
+    target_user: Option<String>,
+    target_user_id: Option<String>,
+    /// Timeout duration in seconds, None for permanent ban or chat clear
+    ban_duration: Option<u32>,
+  },
+  /// Single message got deleted
+  ClearMsg {
+    login: String,
+    target_msg_id: String,
+    text: String,
+  },
+  Notice {
+    channel: String,
+    msg_id: String,
+    text: String,
+  },
+  /// Chat settings of the channel, only changed settings are present in partial updates
+  RoomState {
+    channel: String,
+    room_id: String,
+    emote_only: Option<bool>,
+    /// -1 == disabled, 0 == all followers, >0 == minutes followed
+    followers_only: Option<i32>,
+    unique_chat: Option<bool>,
+    slow: Option<u32>,
+    subs_only: Option<bool>,
+  },
+  /// State of the bot user in the channel, sent after joining and after the bot sends a message
+  UserState {
+    channel: String,
+    display_name: String,
+    badges: Vec<Badge>,
+  },
+  GlobalUserState,
+  Join,
+  Part,
+  Ping(String),
+  Reconnect,
+  /// Not recognized or not handled message
+  Other(IrcMessage),
+}
+
+impl ChatMessage {
+  /// Parses single IRC message line into typed chat message.
+  pub fn parse(line: &str) -> Option<Self> {
+    return IrcMessage::parse(line).map(ChatMessage::from);
+  }
+}
+
+impl From<IrcMessage> for ChatMessage {
+  fn from(msg: IrcMessage) -> Self {
+    match msg.command.as_str() {
+      "PRIVMSG" => {
+        let mut text = msg.param(1).to_string();
+        // Message sent with "/me" command
+        if text.starts_with("\u{1}ACTION ") && text.ends_with('\u{1}') {
+          text = text[8..(text.len() - 1)].to_string(); // 8 == "\u{1}ACTION ".len()
+        }
+        return ChatMessage::Privmsg(Privmsg {
+          channel: channel_name(msg.param(0)),
+          room_id: msg.tag_or_empty("room-id"),
+          message_id: msg.tag_or_empty("id"),
+          user_id: msg.tag_or_empty("user-id"),
+          login: msg.nick().to_string(),
+          display_name: match msg.tag("display-name") {
+            Some(name) => name.to_string(),
+            None => msg.nick().to_string(),
+          },
+          badges: parse_badges(msg.tag("badges")),
+          emotes: emotes::parse(msg.tag("emotes"), &text),
+          text,
+          bits: msg.tag("bits").and_then(|b| b.parse().ok()),
+          custom_reward_id: msg.tag("custom-reward-id").map(|r| r.to_string()),
+          tags: msg.tags,
+          whisper: false,
+        });
+      }
+      "USERNOTICE" => {
+        return ChatMessage::UserNotice(UserNotice {
+          msg_id: msg.tag_or_empty("msg-id"),
+          display_name: match msg.tag("display-name") {
+            Some(name) => name.to_string(),
+            None => msg.tag_or_empty("login"),
+          },
+          system_msg: msg.tag_or_empty("system-msg"),
+          text: msg.param(1).to_string(),
+          tags: msg.tags,
+        });
+      }
+      "CLEARCHAT" => {
+        let target = msg.param(1);
+        return ChatMessage::ClearChat {
+          channel: channel_name(msg.param(0)),
+          target_user: if target.len() > 0 {
+            Some(target.to_string())
+          } else {
+            None
+          },
+          target_user_id: msg.tag("target-user-id").map(|t| t.to_string()),
+          ban_duration: msg.tag("ban-duration").and_then(|d| d.parse().ok()),
+        };
+      }
+      "CLEARMSG" => {
+        return ChatMessage::ClearMsg {
+          login: msg.tag_or_empty("login"),
+          target_msg_id: msg.tag_or_empty("target-msg-id"),
+          text: msg.param(1).to_string(),
+        };
+      }
+      "NOTICE" => {
+        return ChatMessage::Notice {
+          channel: channel_name(msg.param(0)),
+          msg_id: msg.tag_or_empty("msg-id"),
+          text: msg.param(1).to_string(),
+        };
+      }
+      "ROOMSTATE" => {
+        let flag = |key: &str| msg.tag(key).map(|v| v == "1");
+        return ChatMessage::RoomState {
+          channel: channel_name(msg.param(0)),
+          room_id: msg.tag_or_empty("room-id"),
+          emote_only: flag("emote-only"),
+          followers_only: msg.tag("followers-only").and_then(|v| v.parse().ok()),
+          unique_chat: flag("r9k"),
+          slow: msg.tag("slow").and_then(|v| v.parse().ok()),
+          subs_only: flag("subs-only"),
+        };
+      }
+      "USERSTATE" => {
+        return ChatMessage::UserState {
+          channel: channel_name(msg.param(0)),
+          display_name: msg.tag_or_empty("display-name"),
+          badges: parse_badges(msg.tag("badges")),
+        };
+      }
+      "GLOBALUSERSTATE" => {
+        return ChatMessage::GlobalUserState;
+      }
+      "JOIN" => {
+        return ChatMessage::Join;
+      }
+      "PART" => {
+        return ChatMessage::Part;
+      }
+      "PING" => {
+        return ChatMessage::Ping(msg.param(0).to_string());
+      }
+      "RECONNECT" => {
+        return ChatMessage::Reconnect;
+      }
+      _ => {
+        return ChatMessage::Other(msg);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unescapes_tag_values() {
+    assert_eq!(unescape_tag_value("hello\\sworld"), "hello world");
+    assert_eq!(unescape_tag_value("a\\:b"), "a;b");
+    assert_eq!(unescape_tag_value("back\\\\slash"), "back\\slash");
+    assert_eq!(unescape_tag_value("line\\r\\n"), "line\r\n");
+    assert_eq!(unescape_tag_value("\\x"), "x");
+    assert_eq!(unescape_tag_value("trailing\\"), "trailing");
+  }
+
+  #[test]
+  fn parses_full_message() {
+    let msg = IrcMessage::parse(
+      "@badges=broadcaster/1;display-name=User;system-msg=Hi\\sthere :user!user@user.tmi.twitch.tv PRIVMSG #channel :hello world",
+    )
+    .unwrap();
+    assert_eq!(msg.tag("display-name"), Some("User"));
+    assert_eq!(msg.tag("system-msg"), Some("Hi there"));
+    assert_eq!(msg.prefix.as_deref(), Some("user!user@user.tmi.twitch.tv"));
+    assert_eq!(msg.nick(), "user");
+    assert_eq!(msg.command, "PRIVMSG");
+    assert_eq!(msg.params, vec!["#channel", "hello world"]);
+  }
+
+  #[test]
+  fn parses_message_without_prefix() {
+    let msg = IrcMessage::parse("PING :tmi.twitch.tv").unwrap();
+    assert_eq!(msg.tags.len(), 0);
+    assert_eq!(msg.prefix, None);
+    assert_eq!(msg.nick(), "");
+    assert_eq!(msg.command, "PING");
+    assert_eq!(msg.param(0), "tmi.twitch.tv");
+    assert_eq!(msg.param(1), "");
+  }
+
+  #[test]
+  fn parses_empty_tag_values() {
+    let msg = IrcMessage::parse("@emotes=;flag;;id=1 :tmi.twitch.tv USERSTATE #channel").unwrap();
+    assert_eq!(msg.tags.get("emotes").map(|t| t.as_str()), Some(""));
+    assert_eq!(msg.tags.get("flag").map(|t| t.as_str()), Some(""));
+    assert_eq!(msg.tag("emotes"), None);
+    assert_eq!(msg.tag("id"), Some("1"));
+    assert_eq!(msg.tag_or_empty("missing"), "");
+  }
+
+  #[test]
+  fn parses_trailing_parameter() {
+    let msg = IrcMessage::parse(":tmi.twitch.tv 353 bot = #channel :a b  c").unwrap();
+    assert_eq!(msg.params, vec!["bot", "=", "#channel", "a b  c"]);
+
+    let msg = IrcMessage::parse(":tmi.twitch.tv CAP * ACK :").unwrap();
+    assert_eq!(msg.params, vec!["*", "ACK", ""]);
+
+    let msg = IrcMessage::parse(":tmi.twitch.tv JOIN   #channel").unwrap();
+    assert_eq!(msg.params, vec!["#channel"]);
+  }
+
+  #[test]
+  fn rejects_message_without_command() {
+    assert!(IrcMessage::parse("").is_none());
+    assert!(IrcMessage::parse("@id=1 :prefix").is_none());
+  }
+
+  #[test]
+  fn parses_badges() {
+    let badges = parse_badges(Some("moderator/1,subscriber/12,,vip"));
+    let names: Vec<&str> = badges.iter().map(|b| b.name.as_str()).collect();
+    assert_eq!(names, vec!["moderator", "subscriber", "vip"]);
+    assert_eq!(Permission::from_badges(&badges), Permission::Moderator);
+    assert_eq!(parse_badges(None).len(), 0);
+  }
+}
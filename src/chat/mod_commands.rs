@@ -0,0 +1,315 @@
+use std::{thread, time::Duration};
+
+use chrono::Local;
+
+use crate::{database, helix};
+
+use super::{
+  history::{self, HistoryFilter},
+  message::parse_badges,
+  moderation, reply, room_id, Permission, Privmsg,
+};
+
+/// Default timeout duration in seconds
+const DEFAULT_TIMEOUT: u32 = 600;
+/// Default time the permitted user can post links in seconds
+const DEFAULT_PERMIT: u32 = 60;
+/// How far back the chat history is checked by "!nuke" command
+const NUKE_LOOKBACK: Duration = Duration::from_secs(5 * 60);
+/// Default timeout duration of "!nuke" command in seconds
+const NUKE_TIMEOUT: u32 = 60;
+
+/// Handles "!timeout", "!ban", "!unban", "!delete", "!permit" and "!nuke" commands.
+/// Commands calling Twitch API run on a separate thread so they don't hold up the chat.
+pub fn handle_command(msg: &Privmsg, command: &str, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let mut words = args.split_whitespace();
+  let target = words.next().unwrap_or_default().trim_start_matches('@');
+  let rest: Vec<&str> = words.collect();
+  if target.len() == 0 {
+    reply(
+      msg,
+      &format!(
+        "Usage: {} {}",
+        command,
+        match command {
+          "!timeout" => "<user> [seconds] [reason]",
+          "!ban" => "<user> [reason]",
+          "!delete" => "<user> (deletes their last message)",
+          "!permit" => "<user> [seconds]",
+          "!nuke" => "<phrase> [-t=seconds]",
+          _ => "<user>",
+        }
+      ),
+    );
+    return;
+  }
+
+  match command {
+    "!permit" => {
+      let duration = rest
+        .first()
+        .and_then(|d| d.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_PERMIT);
+      moderation::permit(&msg.channel, target, Duration::from_secs(duration as u64));
+      audit(
+        &msg.channel,
+        &msg.display_name,
+        "permit",
+        target,
+        Some(duration),
+        "",
+        true,
+      );
+      reply(
+        msg,
+        &format!("{} can post links for {} s", target, duration),
+      );
+    }
+    "!nuke" => {
+      let mut duration = NUKE_TIMEOUT;
+      let mut phrase = vec![target];
+      for word in rest {
+        match word.strip_prefix("-t=").and_then(|d| d.parse::<u32>().ok()) {
+          Some(d) => duration = d,
+          None => phrase.push(word),
+        }
+      }
+      nuke(msg, &phrase.join(" "), duration);
+    }
+    "!timeout" | "!ban" | "!unban" | "!delete" => {
+      let msg = msg.clone();
+      let command = command.to_string();
+      let target = target.to_string();
+      let rest: Vec<String> = rest.iter().map(|w| w.to_string()).collect();
+      thread::spawn(move || {
+        let result = user_command(&msg, &command, &target, &rest);
+        match result {
+          Ok(answer) => reply(&msg, &answer),
+          Err(err) => reply(&msg, &err),
+        }
+      });
+    }
+    _ => {}
+  }
+}
+
+/// Runs moderation command targeting single user, returns the answer to the moderator
+fn user_command(
+  msg: &Privmsg,
+  command: &str,
+  target: &str,
+  rest: &[String],
+) -> Result<String, String> {
+  match command {
+    "!timeout" => {
+      let (duration, reason) = match rest.first().and_then(|d| d.parse::<u32>().ok()) {
+        Some(duration) => (duration, rest[1..].join(" ")),
+        None => (DEFAULT_TIMEOUT, rest.join(" ")),
+      };
+      timeout(
+        &msg.channel,
+        &msg.display_name,
+        target,
+        Some(duration),
+        &reason,
+      )?;
+      return Ok(format!("{} timed out for {} s", target, duration));
+    }
+    "!ban" => {
+      timeout(
+        &msg.channel,
+        &msg.display_name,
+        target,
+        None,
+        &rest.join(" "),
+      )?;
+      return Ok(format!("{} banned", target));
+    }
+    "!unban" => {
+      unban(&msg.channel, &msg.display_name, target)?;
+      return Ok(format!("{} unbanned", target));
+    }
+    "!delete" => {
+      delete_last(&msg.channel, &msg.display_name, target)?;
+      return Ok(format!("The last message of {} deleted", target));
+    }
+    _ => return Err(format!("Unknown command {}", command)),
+  }
+}
+
+/// Times out the user for `duration` seconds, bans the user if duration is None
+pub fn timeout(
+  channel: &str,
+  moderator: &str,
+  login: &str,
+  duration: Option<u32>,
+  reason: &str,
+) -> Result<(), String> {
+  let (broadcaster_id, user_id) = ids(channel, login)?;
+  return ban(
+    channel,
+    moderator,
+    login,
+    &broadcaster_id,
+    &user_id,
+    duration,
+    reason,
+  );
+}
+
+/// Times out or bans the user with known user ID
+fn ban(
+  channel: &str,
+  moderator: &str,
+  login: &str,
+  broadcaster_id: &str,
+  user_id: &str,
+  duration: Option<u32>,
+  reason: &str,
+) -> Result<(), String> {
+  let success = helix::ban_user(broadcaster_id, user_id, duration, reason);
+  let action = if duration.is_some() { "timeout" } else { "ban" };
+  audit(channel, moderator, action, login, duration, reason, success);
+  if !success {
+    return Err(format!("Couldn't {} {}", action, login));
+  }
+  return Ok(());
+}
+
+/// Removes the ban or timeout of the user
+pub fn unban(channel: &str, moderator: &str, login: &str) -> Result<(), String> {
+  let (broadcaster_id, user_id) = ids(channel, login)?;
+  let success = helix::unban_user(&broadcaster_id, &user_id);
+  audit(channel, moderator, "unban", login, None, "", success);
+  if !success {
+    return Err(format!("Couldn't unban {}", login));
+  }
+  return Ok(());
+}
+
+/// Deletes the newest message the user sent in the channel that is still in the chat
+pub fn delete_last(channel: &str, moderator: &str, login: &str) -> Result<(), String> {
+  let broadcaster_id = room_id(channel).ok_or(format!("Channel #{} is not joined yet", channel))?;
+  let message = history::search(&HistoryFilter {
+    channel: Some(channel.to_string()),
+    user: Some(login.to_string()),
+    limit: Some(20),
+    ..Default::default()
+  })
+  .into_iter()
+  .rev()
+  .find(|m| !m.deleted && m.message_id.len() > 0)
+  .ok_or(format!("{} doesn't have any recent messages", login))?;
+  let success = helix::delete_message(&broadcaster_id, &message.message_id);
+  audit(
+    channel,
+    moderator,
+    "delete",
+    login,
+    None,
+    &message.text,
+    success,
+  );
+  if !success {
+    return Err(format!("Couldn't delete the message of {}", login));
+  }
+  history::mark_message_deleted(&message.message_id);
+  return Ok(());
+}
+
+/// Times out everyone that recently sent a message containing the phrase, moderators and VIPs are skipped.
+/// Timeouts are sent from a separate thread and the result is replied when all of them are done.
+fn nuke(msg: &Privmsg, phrase: &str, duration: u32) {
+  let broadcaster_id = match room_id(&msg.channel) {
+    Some(id) => id,
+    None => {
+      reply(msg, &format!("Channel #{} is not joined yet", msg.channel));
+      return;
+    }
+  };
+  let messages = history::search(&HistoryFilter {
+    channel: Some(msg.channel.clone()),
+    text: Some(phrase.to_string()),
+    from: Some(Local::now() - chrono::Duration::from_std(NUKE_LOOKBACK).unwrap_or_default()),
+    ..Default::default()
+  });
+  // (login, user ID)
+  let mut users: Vec<(String, String)> = Vec::new();
+  for m in messages {
+    if m.user_id.len() == 0
+      || users.iter().any(|u| u.1 == m.user_id)
+      || Permission::from_badges(&parse_badges(Some(&m.badges))) >= Permission::Vip
+    {
+      continue;
+    }
+    users.push((m.login, m.user_id));
+  }
+
+  let msg = msg.clone();
+  let reason = format!("Nuked phrase: {}", phrase);
+  thread::spawn(move || {
+    let mut count = 0;
+    for (login, user_id) in users.iter() {
+      if ban(
+        &msg.channel,
+        &msg.display_name,
+        login,
+        &broadcaster_id,
+        user_id,
+        Some(duration),
+        &reason,
+      )
+      .is_ok()
+      {
+        count += 1;
+      }
+    }
+    reply(&msg, &format!("Nuked {} users for {} s", count, duration));
+  });
+}
+
+/// Returns the broadcaster ID of the channel and the user ID of the user
+fn ids(channel: &str, login: &str) -> Result<(String, String), String> {
+  let broadcaster_id = room_id(channel).ok_or(format!("Channel #{} is not joined yet", channel))?;
+  let user_id = helix::get_user_id(login).ok_or(format!("User {} not found", login))?;
+  return Ok((broadcaster_id, user_id));
+}
+
+/// Stores moderation action in the database
+pub fn audit(
+  channel: &str,
+  moderator: &str,
+  action: &str,
+  target: &str,
+  duration: Option<u32>,
+  reason: &str,
+  success: bool,
+) {
+  log::info!(
+    "Moderation in #{}: {} {} {}{} {}",
+    channel,
+    moderator,
+    action,
+    target,
+    duration
+      .map(|d| format!(" for {} s", d))
+      .unwrap_or_default(),
+    if success { "" } else { "(failed)" }
+  );
+  database::execute(
+    "INSERT INTO ModerationLog (Channel, Moderator, Action, Target, Duration, Reason, Success, Timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
+    &[
+      channel.into(),
+      moderator.into(),
+      action.into(),
+      target.into(),
+      duration.map(|d| d as i64).into(),
+      reason.into(),
+      (success as i64).into(),
+      Local::now().timestamp().into(),
+    ],
+  );
+}
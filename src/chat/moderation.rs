@@ -0,0 +1,615 @@
+use std::{
+  fmt,
+  sync::Mutex,
+  thread,
+  time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use crate::{database, helix};
+
+use super::{mod_commands, reply, send_message_to, Permission, Priority, Privmsg};
+
+/// Moderation filter checking chat messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Filter {
+  /// Links to not whitelisted domains
+  Links,
+  /// Too many upper case letters
+  Caps,
+  /// The same character repeated many times
+  Repeat,
+  /// Too many emotes
+  Emotes,
+  /// Banned phrases and regular expressions
+  Phrases,
+}
+
+impl Filter {
+  const ALL: [Filter; 5] = [
+    Filter::Links,
+    Filter::Caps,
+    Filter::Repeat,
+    Filter::Emotes,
+    Filter::Phrases,
+  ];
+
+  fn name(&self) -> &'static str {
+    match self {
+      Filter::Links => "links",
+      Filter::Caps => "caps",
+      Filter::Repeat => "repeat",
+      Filter::Emotes => "emotes",
+      Filter::Phrases => "phrases",
+    }
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    return Filter::ALL.iter().find(|f| f.name() == name).copied();
+  }
+
+  /// Message sent to the chatter that violated the filter
+  fn warning(&self) -> &'static str {
+    match self {
+      Filter::Links => "please don't post links",
+      Filter::Caps => "please don't use so many capital letters",
+      Filter::Repeat => "please don't spam characters",
+      Filter::Emotes => "please don't spam emotes",
+      Filter::Phrases => "that phrase is not allowed here",
+    }
+  }
+}
+
+/// Action taken when a message violates the filter
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+  /// Only send a warning to the chat
+  Warn,
+  /// Delete the message and send a warning
+  Delete,
+  /// Timeout the user for provided number of seconds and send a warning
+  Timeout(u32),
+}
+
+impl Action {
+  /// Parses action from text like "warn", "delete" or "timeout 600".
+  /// Timeout without duration uses the default one, invalid duration is rejected.
+  fn parse(text: &str) -> Option<Self> {
+    let mut words = text.split_whitespace();
+    match words.next()?.to_lowercase().as_str() {
+      "warn" | "warning" => Some(Action::Warn),
+      "delete" => Some(Action::Delete),
+      "timeout" => match words.next() {
+        Some(duration) => match duration.parse::<u32>() {
+          Ok(d) if d > 0 => Some(Action::Timeout(d)),
+          _ => None,
+        },
+        None => Some(Action::Timeout(DEFAULT_TIMEOUT)),
+      },
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Action::Warn => write!(f, "warn"),
+      Action::Delete => write!(f, "delete"),
+      Action::Timeout(duration) => write!(f, "timeout {}", duration),
+    }
+  }
+}
+
+/// Banned phrase, if it was defined as "/regex/" it is matched as regular expression
+#[derive(Clone)]
+struct BannedPhrase {
+  text: String,
+  regex: Option<Regex>,
+}
+
+/// Moderation rules of the channel, filters without action are disabled
+#[derive(Clone)]
+struct Rules {
+  /// Empty for common rules used in channels without their own rules
+  channel: String,
+  actions: Vec<(Filter, Action)>,
+  /// Minimum percentage of upper case letters in the message that triggers caps filter
+  caps_ratio: u32,
+  /// Minimum number of letters in the message checked by caps filter
+  caps_min_length: u32,
+  /// Maximum number of the same character in a row
+  repeat_max: u32,
+  /// Maximum number of emotes in the message
+  emotes_max: u32,
+  /// Domains that can be posted
+  whitelist: Vec<String>,
+  banned: Vec<BannedPhrase>,
+}
+
+/// Common rules and rules of the channels that changed some of them
+static RULES: Mutex<Vec<Rules>> = Mutex::new(Vec::new());
+/// Users allowed to post links for a while (channel, login, until)
+static PERMITS: Mutex<Vec<(String, String, Instant)>> = Mutex::new(Vec::new());
+/// Default timeout duration in seconds
+const DEFAULT_TIMEOUT: u32 = 600;
+/// Top level domains recognized as links when the text doesn't start with "http://" or "https://"
+const KNOWN_TLDS: &[&str] = &[
+  "com", "net", "org", "info", "biz", "edu", "gov", "io", "gg", "tv", "co", "me", "ly", "be",
+  "xyz", "app", "dev", "live", "link", "site", "online", "shop", "store", "club", "fun", "top",
+  "us", "uk", "eu", "de", "fr", "nl", "pl", "cz", "sk", "ru", "es", "se", "fi", "dk", "ca", "au",
+  "jp", "cn", "br",
+];
+/// Usage of the command managing moderation rules
+const USAGE: &str = "!filter [-global] <links|caps|repeat|emotes|phrases> <off|warn|delete|timeout [seconds]>, \
+  !filter [-global] set <caps|capsmin|repeat|emotes> <number>, !filter [-global] <permit|unpermit> <domain>, \
+  !filter [-global] <ban|unban> <phrase or /regex/>";
+
+/// Loads moderation rules from the database.
+/// Rules of the channel start as a copy of common rules (empty channel) and are changed by the rules of the channel.
+pub fn load() {
+  let mut rows: Vec<(String, String, String)> = Vec::new();
+  database::query(
+    "SELECT Channel, Name, Value FROM Moderation ORDER BY Channel, ID;",
+    &[],
+    |row| {
+      rows.push((
+        row.read::<String, _>(0).unwrap_or_default(),
+        row.read::<String, _>(1).unwrap_or_default(),
+        row.read::<String, _>(2).unwrap_or_default(),
+      ));
+    },
+  );
+
+  let mut all = vec![Rules::new("")];
+  for (channel, name, value) in rows {
+    // Common rules are sorted first so channel rules are copied from complete common rules
+    let index = match all.iter().position(|r| r.channel == channel) {
+      Some(i) => i,
+      None => {
+        let mut rules = all[0].clone();
+        rules.channel = channel;
+        all.push(rules);
+        all.len() - 1
+      }
+    };
+    all[index].apply(&name, &value);
+  }
+  *RULES.lock().unwrap() = all;
+}
+
+/// Checks the message against moderation rules, taking action if any of them is violated.
+/// Returns true if the message violated the rules and shouldn't be processed further.
+/// Broadcaster, moderators and VIPs are exempt.
+pub fn check(msg: &Privmsg) -> bool {
+  if msg.permission() >= Permission::Vip {
+    return false;
+  }
+
+  let permitted = {
+    let now = Instant::now();
+    let mut permits = PERMITS.lock().unwrap();
+    permits.retain(|p| p.2 > now);
+    permits
+      .iter()
+      .any(|p| p.0 == msg.channel && p.1 == msg.login)
+  };
+  let violation = {
+    let all = RULES.lock().unwrap();
+    let rules = match find(&all, &msg.channel) {
+      Some(r) => r,
+      None => return false,
+    };
+    rules
+      .actions
+      .iter()
+      .filter(|(filter, _)| !(permitted && *filter == Filter::Links))
+      .find(|(filter, _)| rules.violates(*filter, msg))
+      .copied()
+  };
+  let (filter, action) = match violation {
+    Some(v) => v,
+    None => return false,
+  };
+
+  log::info!(
+    "{} violated '{}' moderation filter in #{}: {}",
+    msg.display_name,
+    filter.name(),
+    msg.channel,
+    msg.text
+  );
+  if action != Action::Warn {
+    // Helix requests are sent from a separate thread so they don't hold up the chat
+    let channel = msg.channel.clone();
+    let room_id = msg.room_id.clone();
+    let message_id = msg.message_id.clone();
+    let user_id = msg.user_id.clone();
+    let login = msg.login.clone();
+    let reason = format!("Automatic moderation: {}", filter.name());
+    thread::spawn(move || match action {
+      Action::Warn => {}
+      Action::Delete => {
+        let success = helix::delete_message(&room_id, &message_id);
+        mod_commands::audit(
+          &channel, "AutoMod", "delete", &login, None, &reason, success,
+        );
+      }
+      Action::Timeout(duration) => {
+        let success = helix::ban_user(&room_id, &user_id, Some(duration), &reason);
+        mod_commands::audit(
+          &channel,
+          "AutoMod",
+          "timeout",
+          &login,
+          Some(duration),
+          &reason,
+          success,
+        );
+      }
+    });
+  }
+  send_message_to(
+    &msg.channel,
+    &format!("@{}, {}", msg.display_name, filter.warning()),
+    Priority::High,
+  );
+  return true;
+}
+
+/// Allows the user to post links in the channel for provided time
+pub fn permit(channel: &str, login: &str, duration: Duration) {
+  PERMITS.lock().unwrap().push((
+    channel.to_string(),
+    login.trim_start_matches('@').to_lowercase(),
+    Instant::now() + duration,
+  ));
+}
+
+impl Rules {
+  fn new(channel: &str) -> Self {
+    return Self {
+      channel: channel.to_string(),
+      actions: Vec::new(),
+      caps_ratio: 70,
+      caps_min_length: 15,
+      repeat_max: 10,
+      emotes_max: 10,
+      whitelist: Vec::new(),
+      banned: Vec::new(),
+    };
+  }
+
+  /// Applies single rule stored in the database, filter with "off" value is disabled
+  fn apply(&mut self, name: &str, value: &str) {
+    if let Some(filter) = Filter::parse(name) {
+      self.actions.retain(|(f, _)| *f != filter);
+      if let Some(action) = Action::parse(value) {
+        self.actions.push((filter, action));
+      }
+      return;
+    }
+    match name {
+      "caps.ratio" => self.caps_ratio = value.parse().unwrap_or(self.caps_ratio),
+      "caps.min" => self.caps_min_length = value.parse().unwrap_or(self.caps_min_length),
+      "repeat.max" => self.repeat_max = value.parse().unwrap_or(self.repeat_max),
+      "emotes.max" => self.emotes_max = value.parse().unwrap_or(self.emotes_max),
+      "whitelist" => self.whitelist.push(value.to_string()),
+      "whitelist.remove" => self.whitelist.retain(|w| w != value),
+      "banned" => self.banned.push(banned_phrase(value)),
+      "banned.remove" => {
+        let text = value.to_lowercase();
+        self.banned.retain(|b| b.text != text);
+      }
+      _ => log::warn!("Unknown moderation rule '{}'", name),
+    }
+  }
+
+  /// Returns true if the message violates the filter
+  fn violates(&self, filter: Filter, msg: &Privmsg) -> bool {
+    match filter {
+      Filter::Links => {
+        return find_links(&msg.text).iter().any(|domain| {
+          !self
+            .whitelist
+            .iter()
+            .any(|w| domain == w || domain.ends_with(&format!(".{}", w)))
+        });
+      }
+      Filter::Caps => {
+        let letters = msg.text.chars().filter(|c| c.is_alphabetic()).count() as u32;
+        let upper = msg.text.chars().filter(|c| c.is_uppercase()).count() as u32;
+        return letters >= self.caps_min_length && upper * 100 >= letters * self.caps_ratio;
+      }
+      Filter::Repeat => {
+        let (mut longest, mut current, mut last) = (0, 0, None);
+        for c in msg.text.chars() {
+          if c.is_whitespace() {
+            continue;
+          }
+          if Some(c) == last {
+            current += 1;
+          } else {
+            current = 1;
+            last = Some(c);
+          }
+          longest = longest.max(current);
+        }
+        return longest > self.repeat_max;
+      }
+      Filter::Emotes => {
+        return msg.emote_count() as u32 > self.emotes_max;
+      }
+      Filter::Phrases => return self.contains_banned(&msg.text),
+    }
+  }
+
+  fn contains_banned(&self, text: &str) -> bool {
+    let lowercase = text.to_lowercase();
+    return self.banned.iter().any(|b| match &b.regex {
+      Some(regex) => regex.is_match(text),
+      None => lowercase.contains(&b.text),
+    });
+  }
+}
+
+/// Returns rules of the channel, common rules if the channel doesn't have its own
+fn find<'a>(all: &'a [Rules], channel: &str) -> Option<&'a Rules> {
+  return all
+    .iter()
+    .find(|r| r.channel == channel)
+    .or_else(|| all.iter().find(|r| r.channel.len() == 0));
+}
+
+/// Returns true if the text contains any of the banned phrases of the channel
+pub fn is_banned(channel: &str, text: &str) -> bool {
+  let all = RULES.lock().unwrap();
+  return find(&all, channel)
+    .map(|r| r.contains_banned(text))
+    .unwrap_or(false);
+}
+
+/// Returns lowercase domains of the links found in the text.
+/// Words without "http://" or "https://" are links only if they end with a known top level domain.
+fn find_links(text: &str) -> Vec<String> {
+  let mut domains = Vec::new();
+  for word in text.split_whitespace() {
+    let word = word.to_lowercase();
+    let without_scheme = word
+      .trim_start_matches("https://")
+      .trim_start_matches("http://");
+    let has_scheme = without_scheme.len() < word.len();
+    let word = without_scheme;
+    let domain = word.split(['/', '?', '#']).next().unwrap_or_default();
+    let domain = domain.trim_end_matches(|c: char| !c.is_alphanumeric());
+    let parts: Vec<&str> = domain.split('.').collect();
+    if parts.len() < 2 || parts.iter().any(|p| p.len() == 0) {
+      continue;
+    }
+    let tld = parts[parts.len() - 1];
+    if tld.len() >= 2
+      && tld.chars().all(|c| c.is_ascii_alphabetic())
+      && (has_scheme || KNOWN_TLDS.contains(&tld))
+      && parts
+        .iter()
+        .all(|p| p.chars().all(|c| c.is_alphanumeric() || c == '-'))
+    {
+      domains.push(domain.to_string());
+    }
+  }
+  return domains;
+}
+
+/// Creates banned phrase, text in "/regex/" format is compiled as case insensitive regular expression
+fn banned_phrase(text: &str) -> BannedPhrase {
+  let mut regex = None;
+  if text.len() > 2 && text.starts_with('/') && text.ends_with('/') {
+    match Regex::new(&format!("(?i){}", &text[1..(text.len() - 1)])) {
+      Ok(r) => regex = Some(r),
+      Err(err) => log::warn!("Banned phrase regex '{}' is not valid. {}", text, err),
+    }
+  }
+  return BannedPhrase {
+    text: text.to_lowercase(),
+    regex,
+  };
+}
+
+/// Handles "!filter" command, "-global" flag changes the common rules instead of the rules of the channel
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let mut words = args.split_whitespace().peekable();
+  // Common rules are used by all channels, so only the broadcaster can change them
+  let scope = if words.peek() == Some(&"-global") {
+    words.next();
+    if msg.permission() < Permission::Broadcaster {
+      reply(
+        msg,
+        "Only the broadcaster can change common moderation rules",
+      );
+      return;
+    }
+    ""
+  } else {
+    msg.channel.as_str()
+  };
+  let first = words.next().unwrap_or_default().to_lowercase();
+  let rest = words.collect::<Vec<&str>>().join(" ");
+
+  let answer = if first.len() == 0 {
+    summary(scope)
+  } else if let Some(filter) = Filter::parse(&first) {
+    if rest == "off" {
+      set_value(scope, filter.name(), "off");
+      format!("Filter '{}' disabled", filter.name())
+    } else {
+      match Action::parse(&rest) {
+        Some(action) => {
+          set_value(scope, filter.name(), &action.to_string());
+          format!("Filter '{}' enabled, action: {}", filter.name(), action)
+        }
+        None => format!("Usage: {}", USAGE),
+      }
+    }
+  } else {
+    match (first.as_str(), rest.as_str()) {
+      ("set", rest) => {
+        let mut parts = rest.split_whitespace();
+        let name = match parts.next().unwrap_or_default() {
+          "caps" => "caps.ratio",
+          "capsmin" => "caps.min",
+          "repeat" => "repeat.max",
+          "emotes" => "emotes.max",
+          _ => "",
+        };
+        match parts.next().and_then(|v| v.parse::<u32>().ok()) {
+          Some(value) if name.len() > 0 => {
+            set_value(scope, name, &value.to_string());
+            format!("Moderation setting '{}' set to {}", name, value)
+          }
+          _ => format!("Usage: {}", USAGE),
+        }
+      }
+      ("permit", domain) if domain.len() > 0 => {
+        let domain = domain.to_lowercase();
+        add_item(scope, "whitelist", &domain);
+        format!("Links to {} are allowed", domain)
+      }
+      ("unpermit", domain) if domain.len() > 0 => {
+        let domain = domain.to_lowercase();
+        if remove_item(scope, "whitelist", &domain) {
+          format!("Links to {} are no longer allowed", domain)
+        } else {
+          format!("Links to {} were not allowed", domain)
+        }
+      }
+      ("ban", phrase) if phrase.len() > 0 => {
+        add_item(scope, "banned", phrase);
+        "Phrase added to banned phrases".to_string()
+      }
+      ("unban", phrase) if phrase.len() > 0 => {
+        if remove_item(scope, "banned", phrase) {
+          "Phrase removed from banned phrases".to_string()
+        } else {
+          "Phrase is not banned".to_string()
+        }
+      }
+      _ => format!("Usage: {}", USAGE),
+    }
+  };
+  load();
+  reply(msg, &answer);
+}
+
+/// Sets single value moderation setting of the channel in the database, empty channel for common rules
+fn set_value(channel: &str, name: &str, value: &str) {
+  database::execute(
+    "DELETE FROM Moderation WHERE Channel = ? AND Name = ?;",
+    &[channel.into(), name.into()],
+  );
+  database::execute(
+    "INSERT INTO Moderation (Channel, Name, Value) VALUES (?, ?, ?);",
+    &[channel.into(), name.into(), value.into()],
+  );
+}
+
+/// Adds the value to the list setting ("whitelist" or "banned") of the channel,
+/// the value is restored if the channel removed it from the common rules before
+fn add_item(channel: &str, name: &str, value: &str) {
+  database::execute(
+    "DELETE FROM Moderation WHERE Channel = ? AND (Name = ? OR Name = ?) AND Value = ?;",
+    &[
+      channel.into(),
+      name.into(),
+      format!("{}.remove", name).into(),
+      value.into(),
+    ],
+  );
+  database::execute(
+    "INSERT INTO Moderation (Channel, Name, Value) VALUES (?, ?, ?);",
+    &[channel.into(), name.into(), value.into()],
+  );
+}
+
+/// Removes the value from the list setting ("whitelist" or "banned") of the channel.
+/// Values inherited from the common rules are removed only for the channel.
+/// Returns false if the value wasn't in the list.
+fn remove_item(channel: &str, name: &str, value: &str) -> bool {
+  let removed = database::execute_count(
+    "DELETE FROM Moderation WHERE Channel = ? AND Name = ? AND Value = ?;",
+    &[channel.into(), name.into(), value.into()],
+  )
+  .unwrap_or_default();
+  if channel.len() == 0 {
+    return removed > 0;
+  }
+  let mut inherited = false;
+  database::query(
+    "SELECT 1 FROM Moderation WHERE Channel = '' AND Name = ? AND Value = ? LIMIT 1;",
+    &[name.into(), value.into()],
+    |_| inherited = true,
+  );
+  if inherited {
+    add_item(channel, &format!("{}.remove", name), value);
+  }
+  return removed > 0 || inherited;
+}
+
+/// Returns short description of moderation rules of the channel
+fn summary(channel: &str) -> String {
+  let all = RULES.lock().unwrap();
+  let rules = match find(&all, channel) {
+    Some(r) => r,
+    None => return "Moderation rules are not loaded".to_string(),
+  };
+  let mut filters = Vec::new();
+  for filter in Filter::ALL {
+    match rules.actions.iter().find(|(f, _)| *f == filter) {
+      Some((_, action)) => filters.push(format!("{}: {}", filter.name(), action)),
+      None => filters.push(format!("{}: off", filter.name())),
+    }
+  }
+  return format!(
+    "Filters - {}. Caps {}% of min {} letters, max {} repeated characters, max {} emotes, {} allowed domains, {} banned phrases",
+    filters.join(", "),
+    rules.caps_ratio,
+    rules.caps_min_length,
+    rules.repeat_max,
+    rules.emotes_max,
+    rules.whitelist.len(),
+    rules.banned.len()
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_links() {
+    assert_eq!(find_links("check twitch.tv/user now"), vec!["twitch.tv"]);
+    assert_eq!(
+      find_links("https://example.cool/page"),
+      vec!["example.cool"]
+    );
+    assert_eq!(find_links("WWW.Example.COM."), vec!["www.example.com"]);
+    assert!(find_links("Mr.Smith said e.g. this").is_empty());
+    assert!(find_links("version 1.5 is out").is_empty());
+  }
+
+  #[test]
+  fn parses_actions() {
+    assert_eq!(Action::parse("warn"), Some(Action::Warn));
+    assert_eq!(Action::parse("delete"), Some(Action::Delete));
+    assert_eq!(
+      Action::parse("timeout"),
+      Some(Action::Timeout(DEFAULT_TIMEOUT))
+    );
+    assert_eq!(Action::parse("timeout 60"), Some(Action::Timeout(60)));
+    assert_eq!(Action::parse("timeout abc"), None);
+    assert_eq!(Action::parse("off"), None);
+  }
+}
@@ -0,0 +1,184 @@
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::client;
+
+use super::{reply, send_message_to, Permission, Priority, Privmsg};
+
+/// Poll run by the bot in the channel
+struct Poll {
+  channel: String,
+  question: String,
+  options: Vec<String>,
+  /// Votes of the users (user ID, option index)
+  votes: Vec<(String, usize)>,
+}
+
+impl Poll {
+  /// Number of votes of every option
+  fn tallies(&self) -> Vec<usize> {
+    let mut tallies = vec![0; self.options.len()];
+    for (_, option) in self.votes.iter() {
+      tallies[*option] += 1;
+    }
+    return tallies;
+  }
+}
+
+/// Currently open polls
+static POLLS: Mutex<Vec<Poll>> = Mutex::new(Vec::new());
+/// Usage of the poll command
+const USAGE: &str = "!poll \"question\" option 1 | option 2 | option 3, !poll close";
+
+/// Handles "!poll" command opening or closing the poll
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  if args.len() == 0 {
+    reply(msg, &format!("Usage: {}", USAGE));
+    return;
+  }
+  if args.eq_ignore_ascii_case("close") || args.eq_ignore_ascii_case("end") {
+    close(msg);
+    return;
+  }
+
+  let parsed = args.strip_prefix('"').and_then(|rest| {
+    let end = rest.find('"')?;
+    let question = rest[..end].trim().to_string();
+    let options: Vec<String> = rest[(end + 1)..]
+      .split('|')
+      .map(|o| o.trim().to_string())
+      .filter(|o| o.len() > 0)
+      .collect();
+    if question.len() == 0 || options.len() < 2 {
+      return None;
+    }
+    return Some((question, options));
+  });
+  let (question, options) = match parsed {
+    Some(p) => p,
+    None => {
+      reply(msg, &format!("Usage: {}", USAGE));
+      return;
+    }
+  };
+
+  let mut polls = POLLS.lock().unwrap();
+  if polls.iter().any(|p| p.channel == msg.channel) {
+    drop(polls);
+    reply(msg, "Poll is already open, close it first");
+    return;
+  }
+  let poll = Poll {
+    channel: msg.channel.clone(),
+    question,
+    options,
+    votes: Vec::new(),
+  };
+  update_widget(&poll, true);
+  let options = poll
+    .options
+    .iter()
+    .enumerate()
+    .map(|(i, o)| format!("{}) {}", i + 1, o))
+    .collect::<Vec<String>>()
+    .join(", ");
+  let text = format!(
+    "Poll: {} Vote with !vote <number>: {}",
+    poll.question, options
+  );
+  polls.push(poll);
+  drop(polls);
+  send_message_to(&msg.channel, &text, Priority::Normal);
+}
+
+/// Handles "!vote" command, every user has one vote that can be changed while the poll is open
+pub fn handle_vote(msg: &Privmsg, args: &str) {
+  let mut polls = POLLS.lock().unwrap();
+  let poll = match polls.iter_mut().find(|p| p.channel == msg.channel) {
+    Some(p) => p,
+    None => return,
+  };
+  let option = match args.trim_start_matches('#').parse::<usize>() {
+    Ok(n) if n >= 1 && n <= poll.options.len() => n - 1,
+    _ => return,
+  };
+  match poll.votes.iter_mut().find(|(id, _)| *id == msg.user_id) {
+    Some(vote) => vote.1 = option,
+    None => poll.votes.push((msg.user_id.clone(), option)),
+  }
+  update_widget(poll, true);
+}
+
+/// Closes the poll in the channel of the message and sends the results to the chat
+fn close(msg: &Privmsg) {
+  let poll = {
+    let mut polls = POLLS.lock().unwrap();
+    match polls.iter().position(|p| p.channel == msg.channel) {
+      Some(i) => polls.remove(i),
+      None => {
+        drop(polls);
+        reply(msg, "There is no open poll");
+        return;
+      }
+    }
+  };
+  update_widget(&poll, false);
+
+  let tallies = poll.tallies();
+  let max = tallies.iter().copied().max().unwrap_or_default();
+  let results = poll
+    .options
+    .iter()
+    .zip(tallies.iter())
+    .map(|(o, t)| format!("{}: {}", o, t))
+    .collect::<Vec<String>>()
+    .join(", ");
+  let winners = poll
+    .options
+    .iter()
+    .zip(tallies.iter())
+    .filter(|(_, t)| **t == max)
+    .map(|(o, _)| o.as_str())
+    .collect::<Vec<&str>>();
+  let text = if max == 0 {
+    format!("Poll closed without votes: {}", poll.question)
+  } else if winners.len() > 1 {
+    format!(
+      "Poll closed: {} Results - {}. It's a tie between {}!",
+      poll.question,
+      results,
+      winners.join(", ")
+    )
+  } else {
+    format!(
+      "Poll closed: {} Results - {}. {} wins!",
+      poll.question, results, winners[0]
+    )
+  };
+  send_message_to(&msg.channel, &text, Priority::Normal);
+}
+
+/// Sends current tallies to the overlay poll widget
+fn update_widget(poll: &Poll, open: bool) {
+  let options: Vec<serde_json::Value> = poll
+    .options
+    .iter()
+    .zip(poll.tallies())
+    .map(|(o, t)| json!({ "name": o, "votes": t }))
+    .collect();
+  client::send_widget_message(
+    &format!("poll {}", poll.channel),
+    &json!({
+      "widget": "poll",
+      "channel": poll.channel,
+      "question": poll.question,
+      "options": options,
+      "open": open,
+    })
+    .to_string(),
+  );
+}
@@ -0,0 +1,188 @@
+use chrono::{Local, TimeZone};
+use rand::Rng;
+
+use crate::{database, stream};
+
+use super::{commands, reply, Permission, Privmsg};
+
+/// Quote stored in the database
+struct Quote {
+  number: i64,
+  text: String,
+  added_by: String,
+  game: String,
+  /// Unix timestamp in seconds
+  timestamp: i64,
+}
+
+/// Usage of the quote commands
+const USAGE: &str =
+  "!quote [number], !quote add <text>, !quote edit <number> <text>, !quote delete <number>";
+
+/// Handles "!quote" command
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  let (action, rest) = match args.find(' ') {
+    Some(idx) => (&args[..idx], args[(idx + 1)..].trim()),
+    None => (args, ""),
+  };
+  let action = action.to_lowercase();
+
+  let answer = match action.as_str() {
+    "add" | "edit" | "delete" | "del" | "remove" => {
+      if msg.permission() < Permission::Moderator {
+        return;
+      }
+      match action.as_str() {
+        "add" => add(msg, rest),
+        "edit" => edit(msg, rest),
+        _ => delete(msg, rest),
+      }
+    }
+    "" => {
+      if !commands::check_access(msg, "quote", Permission::Everyone, 5, 0) {
+        return;
+      }
+      match random(&msg.channel) {
+        Some(quote) => format_quote(&quote),
+        None => "There are no quotes yet".to_string(),
+      }
+    }
+    number => {
+      if !commands::check_access(msg, "quote", Permission::Everyone, 5, 0) {
+        return;
+      }
+      match number.trim_start_matches('#').parse::<i64>() {
+        Ok(number) => match get(&msg.channel, number) {
+          Some(quote) => format_quote(&quote),
+          None => format!("Quote #{} doesn't exist", number),
+        },
+        Err(_) => format!("Usage: {}", USAGE),
+      }
+    }
+  };
+  reply(msg, &answer);
+}
+
+/// Adds new quote, returns the response to the chat
+fn add(msg: &Privmsg, text: &str) -> String {
+  if text.len() == 0 {
+    return format!("Usage: {}", USAGE);
+  }
+  let mut number = 1;
+  database::query(
+    "SELECT MAX(Number) FROM Quotes WHERE Channel = ?;",
+    &[msg.channel.as_str().into()],
+    |row| {
+      number = row
+        .read::<Option<i64>, _>(0)
+        .unwrap_or_default()
+        .unwrap_or(0)
+        + 1
+    },
+  );
+  let game = stream::game(&msg.channel).unwrap_or_default();
+  if !database::execute(
+    "INSERT INTO Quotes (Channel, Number, Text, AddedBy, Game, Timestamp) VALUES (?, ?, ?, ?, ?, ?);",
+    &[
+      msg.channel.as_str().into(),
+      number.into(),
+      text.into(),
+      msg.display_name.as_str().into(),
+      game.as_str().into(),
+      Local::now().timestamp().into(),
+    ],
+  ) {
+    return "Couldn't add the quote".to_string();
+  }
+  return format!("Quote #{} added", number);
+}
+
+/// Changes the text of existing quote, returns the response to the chat
+fn edit(msg: &Privmsg, args: &str) -> String {
+  let (number, text) = match args.find(' ') {
+    Some(idx) => (&args[..idx], args[(idx + 1)..].trim()),
+    None => (args, ""),
+  };
+  let number = match number.trim_start_matches('#').parse::<i64>() {
+    Ok(n) if text.len() > 0 => n,
+    _ => return format!("Usage: {}", USAGE),
+  };
+  if get(&msg.channel, number).is_none() {
+    return format!("Quote #{} doesn't exist", number);
+  }
+  database::execute(
+    "UPDATE Quotes SET Text = ? WHERE Channel = ? AND Number = ?;",
+    &[text.into(), msg.channel.as_str().into(), number.into()],
+  );
+  return format!("Quote #{} edited", number);
+}
+
+/// Deletes the quote, returns the response to the chat
+fn delete(msg: &Privmsg, args: &str) -> String {
+  let number = match args.trim_start_matches('#').parse::<i64>() {
+    Ok(n) => n,
+    Err(_) => return format!("Usage: {}", USAGE),
+  };
+  if get(&msg.channel, number).is_none() {
+    return format!("Quote #{} doesn't exist", number);
+  }
+  database::execute(
+    "DELETE FROM Quotes WHERE Channel = ? AND Number = ?;",
+    &[msg.channel.as_str().into(), number.into()],
+  );
+  return format!("Quote #{} deleted", number);
+}
+
+/// Returns the quote with provided number
+fn get(channel: &str, number: i64) -> Option<Quote> {
+  let mut quotes = read_quotes(
+    "SELECT Number, Text, AddedBy, Game, Timestamp FROM Quotes WHERE Channel = ? AND Number = ?;",
+    &[channel.into(), number.into()],
+  );
+  return quotes.pop();
+}
+
+/// Returns random quote from the channel
+fn random(channel: &str) -> Option<Quote> {
+  let mut quotes = read_quotes(
+    "SELECT Number, Text, AddedBy, Game, Timestamp FROM Quotes WHERE Channel = ?;",
+    &[channel.into()],
+  );
+  if quotes.len() == 0 {
+    return None;
+  }
+  let index = rand::thread_rng().gen_range(0..quotes.len());
+  return Some(quotes.swap_remove(index));
+}
+
+fn read_quotes(query: &str, params: &[sqlite::Value]) -> Vec<Quote> {
+  let mut quotes = Vec::new();
+  database::query(query, params, |row| {
+    quotes.push(Quote {
+      number: row.read::<i64, _>(0).unwrap_or_default(),
+      text: row.read::<String, _>(1).unwrap_or_default(),
+      added_by: row.read::<String, _>(2).unwrap_or_default(),
+      game: row.read::<String, _>(3).unwrap_or_default(),
+      timestamp: row.read::<i64, _>(4).unwrap_or_default(),
+    });
+  });
+  return quotes;
+}
+
+/// Formats the quote like: #3: "text" [game] 2024-01-31, added by user
+fn format_quote(quote: &Quote) -> String {
+  let date = Local
+    .timestamp_opt(quote.timestamp, 0)
+    .single()
+    .map(|d| d.format("%Y-%m-%d").to_string())
+    .unwrap_or_default();
+  let game = if quote.game.len() > 0 {
+    format!(" [{}]", quote.game)
+  } else {
+    String::new()
+  };
+  return format!(
+    "#{}: \"{}\"{} {}, added by {}",
+    quote.number, quote.text, game, date, quote.added_by
+  );
+}
@@ -0,0 +1,257 @@
+use std::sync::Mutex;
+
+use chrono::Local;
+use rand::Rng;
+
+use crate::{database, notifications};
+
+use super::{reply, send_message_to, Permission, Priority, Privmsg};
+
+/// Raffle of the channel, the latest one is kept so the winners can be drawn after closing it
+struct Raffle {
+  channel: String,
+  /// ID in the database
+  id: i64,
+  keyword: String,
+  /// Number of tickets of subscribers, others get 1
+  sub_luck: u32,
+  open: bool,
+}
+
+/// Entry of the raffle
+struct Entry {
+  user_id: String,
+  display_name: String,
+  tickets: u32,
+}
+
+/// The latest raffle of every channel
+static RAFFLES: Mutex<Vec<Raffle>> = Mutex::new(Vec::new());
+/// Default number of tickets of subscribers
+const DEFAULT_SUB_LUCK: u32 = 2;
+/// Maximum number of tickets of subscribers
+const MAX_SUB_LUCK: u32 = 100;
+/// Usage of the raffle command
+const USAGE: &str =
+  "!raffle open <keyword> [-luck=subscriber tickets], !raffle close, !raffle draw [winners]";
+
+/// Loads raffles that were open when the bot was closed
+pub fn load() {
+  let mut raffles = RAFFLES.lock().unwrap();
+  raffles.clear();
+  database::query(
+    "SELECT ID, Channel, Keyword, SubLuck FROM Raffles WHERE Closed IS NULL;",
+    &[],
+    |row| {
+      raffles.push(Raffle {
+        id: row.read::<i64, _>(0).unwrap_or_default(),
+        channel: row.read::<String, _>(1).unwrap_or_default(),
+        keyword: row.read::<String, _>(2).unwrap_or_default(),
+        sub_luck: row.read::<i64, _>(3).unwrap_or_default() as u32,
+        open: true,
+      });
+    },
+  );
+}
+
+/// Enters the author of the message to the open raffle if the message is the raffle keyword.
+/// Every user is entered only once.
+pub fn check_entry(msg: &Privmsg) {
+  let (id, tickets) = {
+    let raffles = RAFFLES.lock().unwrap();
+    let raffle = match raffles.iter().find(|r| r.channel == msg.channel && r.open) {
+      Some(r) => r,
+      None => return,
+    };
+    if !msg.text.trim().eq_ignore_ascii_case(&raffle.keyword) {
+      return;
+    }
+    let subscriber = msg
+      .badges
+      .iter()
+      .any(|b| b.name == "subscriber" || b.name == "founder");
+    (raffle.id, if subscriber { raffle.sub_luck } else { 1 })
+  };
+  database::execute(
+    "INSERT OR IGNORE INTO RaffleEntries (RaffleID, UserID, Login, DisplayName, Tickets) VALUES (?, ?, ?, ?, ?);",
+    &[
+      id.into(),
+      msg.user_id.as_str().into(),
+      msg.login.as_str().into(),
+      msg.display_name.as_str().into(),
+      (tickets as i64).into(),
+    ],
+  );
+}
+
+/// Handles "!raffle" command
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let answer = match words.first().map(|w| w.to_lowercase()).as_deref() {
+    Some("open") | Some("start") => open(msg, &words[1..]),
+    Some("close") | Some("stop") => close(&msg.channel),
+    Some("draw") => {
+      let count = words
+        .get(1)
+        .and_then(|w| w.parse::<u32>().ok())
+        .unwrap_or(1);
+      draw(&msg.channel, count)
+    }
+    _ => {
+      let raffles = RAFFLES.lock().unwrap();
+      match raffles.iter().find(|r| r.channel == msg.channel && r.open) {
+        Some(r) => format!(
+          "Raffle is open, type {} to enter. {} entries",
+          r.keyword,
+          entries(r.id).len()
+        ),
+        None => format!("Usage: {}", USAGE),
+      }
+    }
+  };
+  reply(msg, &answer);
+}
+
+/// Opens new raffle in the channel of the message
+fn open(msg: &Privmsg, args: &[&str]) -> String {
+  let mut sub_luck = DEFAULT_SUB_LUCK;
+  let mut keyword = None;
+  for arg in args {
+    if let Some(value) = arg.strip_prefix("-luck=") {
+      sub_luck = value
+        .parse::<u32>()
+        .unwrap_or(DEFAULT_SUB_LUCK)
+        .clamp(1, MAX_SUB_LUCK);
+    } else if keyword.is_none() {
+      keyword = Some(arg.to_string());
+    }
+  }
+  let keyword = match keyword {
+    Some(k) => k,
+    None => return format!("Usage: {}", USAGE),
+  };
+
+  let mut raffles = RAFFLES.lock().unwrap();
+  if raffles.iter().any(|r| r.channel == msg.channel && r.open) {
+    return "Raffle is already open, close it first".to_string();
+  }
+  // The ID is returned by the same statement, so it can't be mixed up with a raffle opened at the same time
+  let mut id = None;
+  database::query(
+    "INSERT INTO Raffles (Channel, Keyword, SubLuck, OpenedBy, Opened) VALUES (?, ?, ?, ?, ?) RETURNING ID;",
+    &[
+      msg.channel.as_str().into(),
+      keyword.as_str().into(),
+      (sub_luck as i64).into(),
+      msg.display_name.as_str().into(),
+      Local::now().timestamp().into(),
+    ],
+    |row| id = row.read::<i64, _>(0).ok(),
+  );
+  let id = match id {
+    Some(id) => id,
+    None => return "Couldn't open the raffle".to_string(),
+  };
+  raffles.retain(|r| r.channel != msg.channel);
+  raffles.push(Raffle {
+    channel: msg.channel.clone(),
+    id,
+    keyword: keyword.clone(),
+    sub_luck,
+    open: true,
+  });
+  drop(raffles);
+
+  send_message_to(
+    &msg.channel,
+    &format!(
+      "Raffle is open! Type {} in the chat to enter, subscribers get {} tickets",
+      keyword, sub_luck
+    ),
+    Priority::Normal,
+  );
+  return "Raffle opened".to_string();
+}
+
+/// Closes the raffle so no new entries are accepted
+fn close(channel: &str) -> String {
+  let mut raffles = RAFFLES.lock().unwrap();
+  let raffle = match raffles.iter_mut().find(|r| r.channel == channel && r.open) {
+    Some(r) => r,
+    None => return "There is no open raffle".to_string(),
+  };
+  raffle.open = false;
+  database::execute(
+    "UPDATE Raffles SET Closed = ? WHERE ID = ?;",
+    &[Local::now().timestamp().into(), raffle.id.into()],
+  );
+  return format!(
+    "Raffle closed with {} entries, use !raffle draw to draw the winners",
+    entries(raffle.id).len()
+  );
+}
+
+/// Closes the raffle if it's open and draws the winners from entries that didn't win yet.
+/// Chance of winning depends on the number of tickets.
+fn draw(channel: &str, count: u32) -> String {
+  let id = {
+    let raffles = RAFFLES.lock().unwrap();
+    match raffles.iter().find(|r| r.channel == channel) {
+      Some(r) => r.id,
+      None => return "There is no raffle to draw from".to_string(),
+    }
+  };
+  close(channel);
+
+  let mut entries = entries(id);
+  let mut winners = Vec::new();
+  let mut rng = rand::thread_rng();
+  while winners.len() < count as usize && entries.len() > 0 {
+    let total: u64 = entries.iter().map(|e| e.tickets as u64).sum();
+    let mut ticket = rng.gen_range(0..total);
+    let mut index = 0;
+    while ticket >= entries[index].tickets as u64 {
+      ticket -= entries[index].tickets as u64;
+      index += 1;
+    }
+    let winner = entries.swap_remove(index);
+    database::execute(
+      "UPDATE RaffleEntries SET Winner = 1 WHERE RaffleID = ? AND UserID = ?;",
+      &[id.into(), winner.user_id.as_str().into()],
+    );
+    winners.push(winner.display_name);
+  }
+  if winners.len() == 0 {
+    return "There are no entries left to draw from".to_string();
+  }
+
+  let names = winners.join(", ");
+  log::info!("Raffle in #{} won by {}", channel, names);
+  notifications::add_text_notification(&format!("{} won the raffle!", names));
+  send_message_to(
+    channel,
+    &format!("Congratulations {}, you won the raffle!", names),
+    Priority::Normal,
+  );
+  return format!("{} winners drawn", winners.len());
+}
+
+/// Returns entries of the raffle that didn't win yet
+fn entries(raffle_id: i64) -> Vec<Entry> {
+  let mut entries = Vec::new();
+  database::query(
+    "SELECT UserID, DisplayName, Tickets FROM RaffleEntries WHERE RaffleID = ? AND Winner = 0;",
+    &[raffle_id.into()],
+    |row| {
+      entries.push(Entry {
+        user_id: row.read::<String, _>(0).unwrap_or_default(),
+        display_name: row.read::<String, _>(1).unwrap_or_default(),
+        tickets: row.read::<i64, _>(2).unwrap_or_default().max(1) as u32,
+      });
+    },
+  );
+  return entries;
+}
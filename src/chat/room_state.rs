@@ -0,0 +1,140 @@
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::client;
+
+use super::{check_access, reply, Permission, Privmsg};
+
+/// Chat settings of the channel
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoomState {
+  pub channel: String,
+  /// Minimum time between messages of the same user in seconds, 0 == disabled
+  pub slow: u32,
+  /// Minimum time the user has to be following the channel in minutes, None == disabled
+  pub followers_only: Option<u32>,
+  pub subs_only: bool,
+  pub emote_only: bool,
+  /// Messages have to be unique (r9k mode)
+  pub unique_chat: bool,
+}
+
+impl RoomState {
+  /// Human readable list of enabled modes
+  pub fn describe(&self) -> String {
+    let mut modes = Vec::new();
+    if self.slow > 0 {
+      modes.push(format!("slow mode ({} s)", self.slow));
+    }
+    match self.followers_only {
+      Some(0) => modes.push("followers-only".to_string()),
+      Some(minutes) => modes.push(format!("followers-only ({} min)", minutes)),
+      None => {}
+    }
+    if self.subs_only {
+      modes.push("sub-only".to_string());
+    }
+    if self.emote_only {
+      modes.push("emote-only".to_string());
+    }
+    if self.unique_chat {
+      modes.push("unique chat".to_string());
+    }
+    if modes.len() == 0 {
+      return "no chat modes".to_string();
+    }
+    return modes.join(", ");
+  }
+}
+
+/// Changed chat settings, settings that are None stay unchanged
+#[derive(Default)]
+pub struct RoomStateUpdate {
+  pub slow: Option<u32>,
+  /// Minimum follow time in minutes, -1 disables followers-only mode
+  pub followers_only: Option<i32>,
+  pub subs_only: Option<bool>,
+  pub emote_only: Option<bool>,
+  pub unique_chat: Option<bool>,
+}
+
+/// Chat settings of joined channels
+static STATES: Mutex<Vec<RoomState>> = Mutex::new(Vec::new());
+
+/// Returns chat settings of the channel, None if the bot didn't join it yet
+pub fn get(channel: &str) -> Option<RoomState> {
+  let states = STATES.lock().unwrap();
+  return states.iter().find(|s| s.channel == channel).cloned();
+}
+
+/// Applies full or partial update of the chat settings, only provided settings are changed.
+/// Changes are sent to the overlay.
+pub fn update(channel: &str, changes: RoomStateUpdate) {
+  let state = {
+    let mut states = STATES.lock().unwrap();
+    let index = match states.iter().position(|s| s.channel == channel) {
+      Some(i) => i,
+      None => {
+        states.push(RoomState {
+          channel: channel.to_string(),
+          ..Default::default()
+        });
+        states.len() - 1
+      }
+    };
+    let state = &mut states[index];
+    let previous = state.clone();
+    if let Some(slow) = changes.slow {
+      state.slow = slow;
+    }
+    if let Some(followers_only) = changes.followers_only {
+      state.followers_only = if followers_only < 0 {
+        None
+      } else {
+        Some(followers_only as u32)
+      };
+    }
+    if let Some(subs_only) = changes.subs_only {
+      state.subs_only = subs_only;
+    }
+    if let Some(emote_only) = changes.emote_only {
+      state.emote_only = emote_only;
+    }
+    if let Some(unique_chat) = changes.unique_chat {
+      state.unique_chat = unique_chat;
+    }
+    if *state == previous {
+      return;
+    }
+    state.clone()
+  };
+
+  log::info!("#{} chat modes: {}", channel, state.describe());
+  client::send_widget_message(
+    &format!("modes {}", channel),
+    &json!({
+      "widget": "modes",
+      "channel": state.channel,
+      "slow": state.slow,
+      "followers_only": state.followers_only,
+      "subs_only": state.subs_only,
+      "emote_only": state.emote_only,
+      "unique_chat": state.unique_chat,
+      "text": state.describe(),
+    })
+    .to_string(),
+  );
+}
+
+/// Handles "!modes" command
+pub fn handle_command(msg: &Privmsg) {
+  if !check_access(msg, "modes", Permission::Everyone, 10, 0) {
+    return;
+  }
+  let text = match get(&msg.channel) {
+    Some(state) => format!("Chat modes: {}", state.describe()),
+    None => "Chat modes are not known yet".to_string(),
+  };
+  reply(msg, &text);
+}
@@ -0,0 +1,97 @@
+use std::{
+  sync::Mutex,
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{helix, template};
+
+use super::{reply, room_id, send_message_to, Permission, Priority, Privmsg};
+
+/// Native shoutouts sent by the bot (channel, target user ID, time)
+static SHOUTOUTS: Mutex<Vec<(String, String, Instant)>> = Mutex::new(Vec::new());
+/// Minimum time between native shoutouts of the channel
+const GLOBAL_COOLDOWN: Duration = Duration::from_secs(2 * 60);
+/// Minimum time between native shoutouts of the same target
+const TARGET_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+/// Default chat message, can be changed with "!template ShoutoutChat"
+const DEFAULT_MESSAGE: &str =
+  "Go check out $(touser) at https://twitch.tv/$(touser), they were last playing $(game)!";
+
+/// Handles "!so" command
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let target = args
+    .split_whitespace()
+    .next()
+    .unwrap_or_default()
+    .trim_start_matches('@');
+  if target.len() == 0 {
+    reply(msg, "Usage: !so <user>");
+    return;
+  }
+  // Twitch API requests would hold up the chat
+  let msg = msg.clone();
+  let target = target.to_string();
+  thread::spawn(move || {
+    if let Err(err) = shoutout(&msg.channel, &target) {
+      reply(&msg, &err);
+    }
+  });
+}
+
+/// Shouts out the raider in the main channel
+pub fn raided(channel: &str, login: &str, viewers: i64) {
+  log::info!("#{} raided by {} with {} viewers", channel, login, viewers);
+  if let Err(err) = shoutout(channel, login) {
+    log::warn!("Couldn't shout out the raider. {}", err);
+  }
+}
+
+/// Sends the shoutout message to the chat and native shoutout if it's not on cooldown
+pub fn shoutout(channel: &str, login: &str) -> Result<(), String> {
+  let target_id = helix::get_user_id(login).ok_or(format!("User {} not found", login))?;
+  let ctx = template::Context {
+    channel: channel.to_string(),
+    user: login.to_string(),
+    user_id: target_id.clone(),
+    args: login.to_string(),
+  };
+  send_message_to(
+    channel,
+    &template::render(&template::get("ShoutoutChat", DEFAULT_MESSAGE), &ctx),
+    Priority::Normal,
+  );
+
+  let now = Instant::now();
+  let mut shoutouts = SHOUTOUTS.lock().unwrap();
+  shoutouts.retain(|(_, _, time)| now - *time < TARGET_COOLDOWN);
+  if shoutouts
+    .iter()
+    .any(|(c, _, time)| c == channel && now - *time < GLOBAL_COOLDOWN)
+  {
+    log::info!(
+      "Native shoutout of {} skipped, shoutouts are on cooldown",
+      login
+    );
+    return Ok(());
+  }
+  if shoutouts
+    .iter()
+    .any(|(c, id, _)| c == channel && *id == target_id)
+  {
+    log::info!(
+      "Native shoutout of {} skipped, {} is on cooldown",
+      login,
+      login
+    );
+    return Ok(());
+  }
+  let broadcaster_id = room_id(channel).ok_or(format!("Channel #{} is not joined yet", channel))?;
+  if helix::send_shoutout(&broadcaster_id, &target_id) {
+    shoutouts.push((channel.to_string(), target_id, now));
+  }
+  return Ok(());
+}
@@ -0,0 +1,217 @@
+use std::{
+  sync::Mutex,
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{database, stream, template};
+
+use super::{reply, send_message_to, Permission, Priority, Privmsg};
+
+/// Message periodically sent to the chat
+struct Timer {
+  channel: String,
+  name: String,
+  message: String,
+  /// Minimum time between messages in minutes
+  interval: u32,
+  /// Minimum number of chat messages since last time the timer was sent
+  min_lines: u32,
+  enabled: bool,
+  last_sent: Instant,
+  /// Chat messages received since last time the timer was sent
+  lines: u32,
+}
+
+/// Timers loaded from the database
+static TIMERS: Mutex<Vec<Timer>> = Mutex::new(Vec::new());
+/// Usage of the command managing timers
+const USAGE: &str = "!timer add|edit <name> <minutes> <min chat lines> <message>, !timer delete <name>, !timer on|off <name>";
+
+/// Loads timers from the database
+pub fn load() {
+  let mut timers = TIMERS.lock().unwrap();
+  timers.clear();
+  database::query(
+    "SELECT Channel, Name, Message, Interval, MinLines, Enabled FROM Timers;",
+    &[],
+    |row| {
+      timers.push(Timer {
+        channel: row.read::<String, _>(0).unwrap_or_default(),
+        name: row.read::<String, _>(1).unwrap_or_default(),
+        message: row.read::<String, _>(2).unwrap_or_default(),
+        interval: row.read::<i64, _>(3).unwrap_or_default() as u32,
+        min_lines: row.read::<i64, _>(4).unwrap_or_default() as u32,
+        enabled: row.read::<i64, _>(5).unwrap_or_default() != 0,
+        last_sent: Instant::now(),
+        lines: 0,
+      });
+    },
+  );
+  log::info!("Loaded {} chat timers", timers.len());
+}
+
+/// Counts chat message for the timers of the channel
+pub fn message_received(channel: &str) {
+  let mut timers = TIMERS.lock().unwrap();
+  for t in timers.iter_mut().filter(|t| t.channel == channel) {
+    t.lines = t.lines.saturating_add(1);
+  }
+}
+
+/// Sends timers that are due, only while the stream is online
+pub fn update() {
+  let now = Instant::now();
+  // (channel, message)
+  let mut due: Vec<(String, String)> = Vec::new();
+  {
+    let mut timers = TIMERS.lock().unwrap();
+    for t in timers.iter_mut() {
+      if !t.enabled
+        || now - t.last_sent < Duration::from_secs(t.interval as u64 * 60)
+        || t.lines < t.min_lines
+        || !stream::is_online(&t.channel)
+      {
+        continue;
+      }
+      t.last_sent = now;
+      t.lines = 0;
+      due.push((t.channel.clone(), t.message.clone()));
+    }
+  }
+
+  for (channel, message) in due {
+    if template::uses_helix(&message) {
+      // Twitch API requests would hold up the chat
+      thread::spawn(move || send(&channel, &message));
+    } else {
+      send(&channel, &message);
+    }
+  }
+}
+
+/// Renders the message of the timer and sends it
+fn send(channel: &str, message: &str) {
+  let ctx = template::Context {
+    channel: channel.to_string(),
+    user: String::new(),
+    user_id: String::new(),
+    args: String::new(),
+  };
+  send_message_to(channel, &template::render(message, &ctx), Priority::Low);
+}
+
+/// Handles "!timer" command
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let mut words = args.split_whitespace();
+  let action = words.next().unwrap_or_default().to_lowercase();
+  let name = words.next().unwrap_or_default().to_lowercase();
+
+  let mut timers = TIMERS.lock().unwrap();
+  if name.len() == 0 {
+    let list: Vec<String> = timers
+      .iter()
+      .filter(|t| t.channel == msg.channel)
+      .map(|t| {
+        format!(
+          "{} ({} min, {} lines{})",
+          t.name,
+          t.interval,
+          t.min_lines,
+          if t.enabled { "" } else { ", off" }
+        )
+      })
+      .collect();
+    drop(timers);
+    reply(
+      msg,
+      &format!(
+        "Timers: {}. Usage: {}",
+        if list.len() > 0 {
+          list.join(", ")
+        } else {
+          "none".to_string()
+        },
+        USAGE
+      ),
+    );
+    return;
+  }
+
+  let index = timers
+    .iter()
+    .position(|t| t.channel == msg.channel && t.name == name);
+  let answer = match (action.as_str(), index) {
+    ("add", Some(_)) => format!("Timer {} already exists", name),
+    ("edit" | "delete" | "del" | "remove" | "on" | "off", None) => {
+      format!("Timer {} doesn't exist", name)
+    }
+    ("add" | "edit", _) => {
+      let interval = words.next().and_then(|w| w.parse::<u32>().ok());
+      let min_lines = words.next().and_then(|w| w.parse::<u32>().ok());
+      let message = words.collect::<Vec<&str>>().join(" ");
+      match (interval, min_lines) {
+        (Some(interval), Some(min_lines)) if interval > 0 && message.len() > 0 => {
+          let timer = Timer {
+            channel: msg.channel.clone(),
+            name: name.clone(),
+            message,
+            interval,
+            min_lines,
+            enabled: true,
+            last_sent: Instant::now(),
+            lines: 0,
+          };
+          save(&timer);
+          match index {
+            Some(i) => timers[i] = timer,
+            None => timers.push(timer),
+          }
+          format!(
+            "Timer {} will be sent every {} minutes if there were at least {} chat messages",
+            name, interval, min_lines
+          )
+        }
+        _ => format!("Usage: {}", USAGE),
+      }
+    }
+    ("delete" | "del" | "remove", Some(i)) => {
+      timers.remove(i);
+      database::execute(
+        "DELETE FROM Timers WHERE Channel = ? AND Name = ?;",
+        &[msg.channel.as_str().into(), name.as_str().into()],
+      );
+      format!("Timer {} deleted", name)
+    }
+    ("on" | "off", Some(i)) => {
+      timers[i].enabled = action == "on";
+      timers[i].last_sent = Instant::now();
+      timers[i].lines = 0;
+      save(&timers[i]);
+      format!("Timer {} turned {}", name, action)
+    }
+    _ => format!("Usage: {}", USAGE),
+  };
+  drop(timers);
+  reply(msg, &answer);
+}
+
+/// Stores the timer in the database
+fn save(timer: &Timer) {
+  database::execute(
+    "INSERT INTO Timers (Channel, Name, Message, Interval, MinLines, Enabled) VALUES (?, ?, ?, ?, ?, ?) \
+    ON CONFLICT(Channel, Name) DO UPDATE SET Message = excluded.Message, Interval = excluded.Interval, \
+    MinLines = excluded.MinLines, Enabled = excluded.Enabled;",
+    &[
+      timer.channel.as_str().into(),
+      timer.name.as_str().into(),
+      timer.message.as_str().into(),
+      (timer.interval as i64).into(),
+      (timer.min_lines as i64).into(),
+      (timer.enabled as i64).into(),
+    ],
+  );
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::{secrets, stream};
+
+use super::{
+  check_for_commands, commands, counters, limiter, message::Badge, moderation, reply,
+  send_message_to, timers, Priority, Privmsg,
+};
+
+/// Handles received whisper. Commands sent in whispers are handled like chat commands in the main channel
+/// and answered with whispers. The broadcaster can also use whisper-only admin commands.
+pub fn handle_whisper(user_id: &str, login: &str, display_name: &str, text: &str) {
+  println!("> Whisper from {}: {}", display_name, text);
+
+  let broadcaster = user_id == secrets::get_data(secrets::Keys::ChannelID);
+  let mut badges = Vec::new();
+  if broadcaster {
+    badges.push(Badge {
+      name: "broadcaster".to_string(),
+    });
+  }
+  let msg = Privmsg {
+    channel: secrets::get_data(secrets::Keys::Channel),
+    room_id: secrets::get_data(secrets::Keys::ChannelID),
+    message_id: String::new(),
+    user_id: user_id.to_string(),
+    login: login.to_string(),
+    display_name: display_name.to_string(),
+    badges,
+    text: text.trim().to_string(),
+    emotes: Vec::new(),
+    bits: None,
+    custom_reward_id: None,
+    tags: HashMap::new(),
+    whisper: true,
+  };
+
+  if broadcaster && handle_admin_command(&msg) {
+    return;
+  }
+  check_for_commands(&msg);
+}
+
+/// Handles whisper-only commands of the broadcaster. Returns false if the message isn't admin command.
+fn handle_admin_command(msg: &Privmsg) -> bool {
+  let (command, args) = match msg.text.find(' ') {
+    Some(idx) => (&msg.text[..idx], msg.text[(idx + 1)..].trim()),
+    None => (msg.text.as_str(), ""),
+  };
+  match command.to_lowercase().as_str() {
+    "!say" => {
+      if args.len() > 0 {
+        send_message_to(&msg.channel, args, Priority::High);
+      }
+    }
+    "!reload" => {
+      commands::load();
+      moderation::load();
+      counters::load();
+      timers::load();
+      crate::scripts::load();
+      reply(
+        msg,
+        "Commands, moderation rules, counters, timers and scripts reloaded",
+      );
+    }
+    "!status" => {
+      let (queued, oldest) = limiter::backlog();
+      reply(
+        msg,
+        &format!(
+          "Stream {}. Chat send queue: {} messages, oldest waiting {} s",
+          if stream::is_online(&msg.channel) {
+            "online"
+          } else {
+            "offline"
+          },
+          queued,
+          oldest.as_secs()
+        ),
+      );
+    }
+    "!admin" => {
+      reply(msg, "Whisper commands: !say <text>, !reload, !status");
+    }
+    _ => return false,
+  }
+  return true;
+}
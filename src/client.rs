@@ -6,10 +6,12 @@ use std::{
   time::Duration,
 };
 
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde_json::json;
 use tiny_http::{Header, Response, Server, StatusCode};
 use tungstenite::Message;
 
-use crate::{notifications, secrets};
+use crate::{chat, notifications, secrets, tts};
 
 const INDEX_HTML: &str = include_str!("client/client.html");
 const CLIENT_JS: &str = include_str!("client/client.js");
@@ -33,6 +35,8 @@ impl Client {
 }
 
 static CONNECTED_CLIENTS: Mutex<Vec<Arc<RwLock<Client>>>> = Mutex::new(Vec::new());
+/// Last state of every overlay widget (widget key, message), sent to newly connected clients
+static WIDGET_STATES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
 
 pub fn start() {
   let server_ip = secrets::get_data(secrets::Keys::ServerIP);
@@ -57,7 +61,12 @@ fn update_new_clients(http_address: String) {
   let server = Server::http(http_address).unwrap();
 
   for request in server.incoming_requests() {
-    match request.url() {
+    let url = request.url().to_string();
+    let (path, query) = match url.find('?') {
+      Some(idx) => (&url[..idx], &url[(idx + 1)..]),
+      None => (url.as_str(), ""),
+    };
+    match path {
       "/" => {
         let resp = Response::from_string(INDEX_HTML).with_header(Header {
           field: "Content-Type".parse().unwrap(),
@@ -85,6 +94,68 @@ fn update_new_clients(http_address: String) {
           .respond(resp)
           .expect("Couldn't respond to the request");
       }
+      "/tts" => {
+        let audio = parse_query(query)
+          .iter()
+          .find(|(k, _)| k == "id")
+          .and_then(|(_, v)| v.parse::<u32>().ok())
+          .and_then(tts::get_audio);
+        let resp = match audio {
+          Some(data) => Response::from_data(data).with_header(Header {
+            field: "Content-Type".parse().unwrap(),
+            value: "audio/wav".parse().unwrap(),
+          }),
+          None => Response::from_data(Vec::new()).with_status_code(StatusCode(404)),
+        };
+        request
+          .respond(resp)
+          .expect("Couldn't respond to the request");
+      }
+      "/emote" => {
+        let image = parse_query(query)
+          .iter()
+          .find(|(k, _)| k == "name")
+          .and_then(|(_, v)| chat::emotes::get_image(v));
+        let resp = match image {
+          Some(data) => Response::from_data(data),
+          None => Response::from_data(Vec::new()).with_status_code(StatusCode(404)),
+        };
+        request
+          .respond(resp)
+          .expect("Couldn't respond to the request");
+      }
+      "/walkon" => {
+        let sound = parse_query(query)
+          .iter()
+          .find(|(k, _)| k == "id")
+          .and_then(|(_, v)| chat::greetings::walkon_file(v))
+          .and_then(|file| std::fs::read(file).ok());
+        let resp = match sound {
+          Some(data) => Response::from_data(data),
+          None => Response::from_data(Vec::new()).with_status_code(StatusCode(404)),
+        };
+        request
+          .respond(resp)
+          .expect("Couldn't respond to the request");
+      }
+      "/history" => {
+        // Chat history is available only on the computer running the bot
+        let local = request
+          .remote_addr()
+          .map(|a| a.ip().is_loopback())
+          .unwrap_or(false);
+        let resp = if local {
+          Response::from_string(chat_history(query)).with_header(Header {
+            field: "Content-Type".parse().unwrap(),
+            value: "application/json; charset=UTF-8".parse().unwrap(),
+          })
+        } else {
+          Response::from_string("").with_status_code(StatusCode(403))
+        };
+        request
+          .respond(resp)
+          .expect("Couldn't respond to the request");
+      }
       _ => {
         let response = Response::new_empty(StatusCode(204));
         request
@@ -95,6 +166,109 @@ fn update_new_clients(http_address: String) {
   }
 }
 
+/// Searches chat history using query parameters:
+/// channel, user, text, from, to (dates like "2024-01-31" or "2024-01-31 18:00:00"), limit.
+/// Returns the messages as json array.
+fn chat_history(query: &str) -> String {
+  let mut filter = chat::history::HistoryFilter::default();
+  for (key, value) in parse_query(query) {
+    if value.len() == 0 {
+      continue;
+    }
+    match key.as_str() {
+      "channel" => filter.channel = Some(value),
+      "user" => filter.user = Some(value),
+      "text" => filter.text = Some(value),
+      "from" => filter.from = parse_time(&value),
+      "to" => filter.to = parse_time(&value),
+      "limit" => filter.limit = value.parse().ok(),
+      _ => {}
+    }
+  }
+
+  let entries: Vec<serde_json::Value> = chat::history::search(&filter)
+    .iter()
+    .map(|e| e.to_json())
+    .collect();
+  return json!(entries).to_string();
+}
+
+/// Parses url query string ("a=1&b=text%20here") into decoded key - value pairs
+fn parse_query(query: &str) -> Vec<(String, String)> {
+  let mut params = Vec::new();
+  for pair in query.split('&') {
+    if pair.len() == 0 {
+      continue;
+    }
+    let (key, value) = match pair.find('=') {
+      Some(idx) => (&pair[..idx], &pair[(idx + 1)..]),
+      None => (pair, ""),
+    };
+    params.push((url_decode(key), url_decode(value)));
+  }
+  return params;
+}
+
+/// Encodes the text to be used as url query value, only unreserved characters are kept unchanged
+pub fn url_encode(text: &str) -> String {
+  let mut encoded = String::with_capacity(text.len());
+  for b in text.bytes() {
+    if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+      encoded.push(b as char);
+    } else {
+      encoded.push_str(&format!("%{:02X}", b));
+    }
+  }
+  return encoded;
+}
+
+/// Decodes url encoded text ('+' and "%XX" sequences), invalid sequences are kept unchanged
+fn url_decode(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'+' => decoded.push(b' '),
+      b'%'
+        if i + 2 < bytes.len()
+          && bytes[i + 1].is_ascii_hexdigit()
+          && bytes[i + 2].is_ascii_hexdigit() =>
+      {
+        let hex = String::from_utf8_lossy(&bytes[(i + 1)..(i + 3)]);
+        decoded.push(u8::from_str_radix(&hex, 16).unwrap_or_default());
+        i += 2;
+      }
+      b => decoded.push(b),
+    }
+    i += 1;
+  }
+  return String::from_utf8_lossy(&decoded).into_owned();
+}
+
+/// Parses local date and time ("2024-01-31", "2024-01-31 18:00", "2024-01-31 18:00:00") or RFC 3339 timestamp
+fn parse_time(text: &str) -> Option<DateTime<Local>> {
+  if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+    return Some(time.with_timezone(&Local));
+  }
+  for format in [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+  ] {
+    if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+      return Local.from_local_datetime(&time).earliest();
+    }
+  }
+  if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+    return Local
+      .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+      .earliest();
+  }
+  return None;
+}
+
 fn update_websockets(websocket_address: String) {
   log::info!("Client websocket start");
 
@@ -111,6 +285,13 @@ fn update_websockets(websocket_address: String) {
       let client = Arc::new(RwLock::new(Client::new(
         websocket.get_ref().peer_addr().unwrap(),
       )));
+      {
+        let mut c = client.write().unwrap();
+        for (_, msg) in WIDGET_STATES.lock().unwrap().iter() {
+          c.queue.push_back(Message::Text(msg.clone()));
+          c.new_msg = true;
+        }
+      }
       {
         CONNECTED_CLIENTS.lock().unwrap().push(client.clone());
       }
@@ -172,6 +353,31 @@ fn update_websockets(websocket_address: String) {
 }
 
 pub fn send_text_message(msg: &str) -> bool {
+  return broadcast(msg, true);
+}
+
+/// Sends widget update (json with "widget" field) to the clients.
+/// Widget updates are not notifications, the clients don't report them as finished.
+/// The last update with the same `key` is remembered and sent to newly connected clients.
+pub fn send_widget_message(key: &str, msg: &str) -> bool {
+  {
+    let mut states = WIDGET_STATES.lock().unwrap();
+    match states.iter_mut().find(|(k, _)| k == key) {
+      Some(state) => state.1 = msg.to_owned(),
+      None => states.push((key.to_owned(), msg.to_owned())),
+    }
+  }
+  return broadcast(msg, false);
+}
+
+/// Sends one-off widget event (json with "widget" field) to the clients, it's not remembered for new clients.
+pub fn send_event_message(msg: &str) -> bool {
+  return broadcast(msg, false);
+}
+
+/// Queues the message to be sent to every connected client.
+/// If `notification` is true, the clients have to report it as finished.
+fn broadcast(msg: &str, notification: bool) -> bool {
   let clients = CONNECTED_CLIENTS.lock().unwrap();
   if clients.len() == 0 {
     return false;
@@ -180,7 +386,9 @@ pub fn send_text_message(msg: &str) -> bool {
   for i in 0..clients.len() {
     let mut c = clients[i].write().unwrap();
     c.queue.push_back(Message::Text(msg.to_owned()));
-    c.finished = false;
+    if notification {
+      c.finished = false;
+    }
     c.new_msg = true;
   }
   return true;
@@ -204,3 +412,55 @@ fn check_clients_finished(clients: Option<std::sync::MutexGuard<'_, Vec<Arc<RwLo
   // All of the clients finished their notifications
   notifications::NOTIFICATION_FINISHED.lock().unwrap()[0] = true;
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decodes_percent_sequences() {
+    assert_eq!(url_decode("text%20here"), "text here");
+    assert_eq!(url_decode("%2Fa%2fb"), "/a/b");
+    assert_eq!(url_decode("%C5%BC%C3%B3%C5%82w"), "\u{17c}\u{f3}\u{142}w");
+    assert_eq!(url_decode("100%25"), "100%");
+  }
+
+  #[test]
+  fn decodes_plus_as_space() {
+    assert_eq!(url_decode("a+b++c"), "a b  c");
+    assert_eq!(url_decode("a%2Bb"), "a+b");
+  }
+
+  #[test]
+  fn keeps_invalid_sequences() {
+    assert_eq!(url_decode("%"), "%");
+    assert_eq!(url_decode("50%"), "50%");
+    assert_eq!(url_decode("%4"), "%4");
+    assert_eq!(url_decode("%zz"), "%zz");
+    assert_eq!(url_decode("%+1"), "% 1");
+    assert_eq!(url_decode("%%41"), "%A");
+    assert_eq!(url_decode("%FF"), "\u{fffd}");
+  }
+
+  #[test]
+  fn encodes_query_values() {
+    assert_eq!(url_encode("Kappa"), "Kappa");
+    assert_eq!(url_encode("a b&c=d"), "a%20b%26c%3Dd");
+    assert_eq!(url_encode("<3"), "%3C3");
+    for text in ["a+b %", "\u{17c}\u{f3}\u{142}w", "emote?name=x#y"] {
+      assert_eq!(url_decode(&url_encode(text)), text);
+    }
+  }
+
+  #[test]
+  fn parses_query_pairs() {
+    assert_eq!(
+      parse_query("channel=abc&text=hello+there&&flag"),
+      vec![
+        ("channel".to_string(), "abc".to_string()),
+        ("text".to_string(), "hello there".to_string()),
+        ("flag".to_string(), String::new()),
+      ]
+    );
+  }
+}
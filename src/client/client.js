@@ -1,13 +1,17 @@
 const ws = new WebSocket("ws://" + window.location.hostname + ":40001");
 let conn_err;
 let content;
+let widgets;
 let audio_player;
+let tts_player;
 let video_player;
 
 function loaded() {
   conn_err = document.getElementById("conn_err");
   content = document.getElementById("content");
+  widgets = document.getElementById("widgets");
   audio_player = document.createElement("audio");
+  tts_player = document.createElement("audio");
   video_player = document.createElement("video");
 
   document.head.innerHTML += `
@@ -23,6 +27,43 @@ function loaded() {
       video {
         position: absolute;
       }
+      .poll {
+        color: white;
+        font-size: 36px;
+        font-family: Calibri;
+        -webkit-text-stroke: 1px black;
+        margin: 0;
+      }
+      .chat {
+        color: white;
+        font-size: 28px;
+        font-family: Calibri;
+        -webkit-text-stroke: 1px black;
+        margin: 0;
+      }
+      .chat img {
+        height: 1.2em;
+        vertical-align: middle;
+      }
+      .wall_emote {
+        position: fixed;
+        height: 56px;
+        transition: opacity 1s;
+      }
+      .combo {
+        color: gold;
+        font-size: 48px;
+        font-family: Calibri;
+        -webkit-text-stroke: 1px black;
+        margin: 0;
+      }
+      .counter {
+        color: white;
+        font-size: 48px;
+        font-family: Calibri;
+        -webkit-text-stroke: 1px black;
+        margin: 0;
+      }
     </style>`;
 }
 
@@ -32,18 +73,26 @@ ws.addEventListener("open", () => {
   console.log("WebSocket connection established!");
   conn_err.hidden = true;
   content.hidden = false;
+  widgets.hidden = false;
 })
 
 ws.addEventListener("close", () => {
   console.log("WebSocket connection closed!");
   conn_err.hidden = false;
   content.hidden = true;
+  widgets.hidden = true;
 });
 
 ws.addEventListener("message", e => {
   let data = JSON.parse(e.data);
   // console.log(data);
 
+  // Widget updates are not notifications, don't touch current notification
+  if (data.widget?.length > 0) {
+    update_widget(data);
+    return;
+  }
+
   // Clear previous child nodes
   clear_content();
   window.clearTimeout();
@@ -56,6 +105,14 @@ ws.addEventListener("message", e => {
     audio_player.play();
   }
 
+  // Read text
+  tts_player.onended = null;
+  if (data.message_read?.length > 0) {
+    tts_player.pause();
+    tts_player.src = data.message_read;
+    tts_player.play();
+  }
+
   // Play video
   if (data.played_video?.length > 0) {
     video_player.pause();
@@ -86,6 +143,9 @@ ws.addEventListener("message", e => {
   } else if (data.type == 2) {
     // Sub notification - wait for video to finish
     video_player.addEventListener("ended", finished, false);
+  } else if (data.message_read?.length > 0) {
+    // Text to speech - wait for the text to be read
+    tts_player.onended = () => window.setTimeout(finished, 500);
   } else {
     // Not recognized message? 2 sec timeout?
     window.setTimeout(finished, 2000);
@@ -108,3 +168,130 @@ function clear_content() {
     content.removeChild(element);
   });
 }
+
+function update_widget(data) {
+  if (data.widget == "counter") {
+    let id = "counter_" + data.channel + "_" + data.name;
+    let counter = document.getElementById(id);
+    if (data.removed) {
+      counter?.remove();
+      return;
+    }
+    if (!counter) {
+      counter = document.createElement("p");
+      counter.id = id;
+      counter.className = "counter";
+      widgets.appendChild(counter);
+    }
+    counter.textContent = data.name + ": " + data.value;
+  } else if (data.widget == "modes") {
+    let id = "modes_" + data.channel;
+    let modes = document.getElementById(id);
+    if (!modes) {
+      modes = document.createElement("p");
+      modes.id = id;
+      modes.className = "counter";
+      widgets.appendChild(modes);
+    }
+    modes.textContent = "Chat: " + data.text;
+  } else if (data.widget == "chat") {
+    show_chat_message(data);
+    show_emote_wall(data);
+    show_combo(data);
+  } else if (data.widget == "poll") {
+    let id = "poll_" + data.channel;
+    let poll = document.getElementById(id);
+    if (!poll) {
+      if (!data.open) {
+        return; // Closed poll that wasn't displayed
+      }
+      poll = document.createElement("div");
+      poll.id = id;
+      poll.className = "poll";
+      widgets.appendChild(poll);
+    }
+    let total = data.options.reduce((sum, o) => sum + o.votes, 0);
+    poll.replaceChildren();
+    let question = document.createElement("p");
+    question.textContent = data.question;
+    poll.appendChild(question);
+    data.options.forEach((o, i) => {
+      let option = document.createElement("p");
+      let percent = total > 0 ? Math.round(o.votes * 100 / total) : 0;
+      option.textContent = (i + 1) + ") " + o.name + " - " + o.votes + " (" + percent + "%)";
+      poll.appendChild(option);
+    });
+    if (!data.open) {
+      // Show final results for a while
+      window.setTimeout(() => poll.remove(), 15000);
+    }
+  }
+}
+
+// Number of chat messages shown in the chat overlay
+const CHAT_LINES = 10;
+
+function show_chat_message(data) {
+  let chat = document.getElementById("chat");
+  if (!chat) {
+    chat = document.createElement("div");
+    chat.id = "chat";
+    widgets.appendChild(chat);
+  }
+  let line = document.createElement("p");
+  line.className = "chat";
+  line.appendChild(document.createTextNode(data.user + ": "));
+  // Emote positions are in characters (code points), not UTF-16 units
+  let chars = Array.from(data.text);
+  let position = 0;
+  data.emotes.forEach(e => {
+    line.appendChild(document.createTextNode(chars.slice(position, e.start).join("")));
+    let img = document.createElement("img");
+    img.src = e.url;
+    img.alt = e.name;
+    line.appendChild(img);
+    position = e.end + 1;
+  });
+  line.appendChild(document.createTextNode(chars.slice(position).join("")));
+  chat.appendChild(line);
+  while (chat.childNodes.length > CHAT_LINES) {
+    chat.removeChild(chat.firstChild);
+  }
+}
+
+function show_emote_wall(data) {
+  data.emotes.forEach(e => {
+    let img = document.createElement("img");
+    img.src = e.url;
+    img.className = "wall_emote";
+    img.style.left = Math.random() * (window.innerWidth - 56) + "px";
+    img.style.top = Math.random() * (window.innerHeight - 56) + "px";
+    document.body.appendChild(img);
+    window.setTimeout(() => img.style.opacity = 0, 3000);
+    window.setTimeout(() => img.remove(), 4000);
+  });
+}
+
+function show_combo(data) {
+  let id = "combo_" + data.channel;
+  let combo = document.getElementById(id);
+  if (data.combo == 0) {
+    combo?.remove();
+    return;
+  }
+  if (!combo) {
+    combo = document.createElement("p");
+    combo.id = id;
+    combo.className = "combo";
+    widgets.appendChild(combo);
+  }
+  let emote = data.emotes[0];
+  combo.replaceChildren();
+  combo.appendChild(document.createTextNode(data.combo + "x "));
+  let img = document.createElement("img");
+  img.src = emote.url;
+  img.alt = emote.name;
+  img.style.height = "1em";
+  combo.appendChild(img);
+  combo.appendChild(document.createTextNode(" COMBO"));
+}
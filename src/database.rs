@@ -1,6 +1,6 @@
 use std::sync::Mutex;
 
-use sqlite::Connection;
+use sqlite::{Connection, State, Statement, Value};
 
 struct Record {
   key: Keys,
@@ -25,7 +25,102 @@ pub enum Keys {
 }
 
 static FILE: &str = ".db";
+/// How long the statement waits for other connections to finish writing before it fails
+const BUSY_TIMEOUT_MS: usize = 5000;
 static DATA: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+/// Additional tables created if they are missing in the database (name, column definitions)
+static TABLES: &[(&str, &str)] = &[
+  (
+    "Commands",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL DEFAULT '', Name TEXT NOT NULL, Response TEXT NOT NULL, \
+  Permission TEXT NOT NULL DEFAULT 'Everyone', GlobalCooldown INTEGER NOT NULL DEFAULT 0, UserCooldown INTEGER NOT NULL DEFAULT 0, \
+  PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(Channel, Name)",
+  ),
+  (
+    "ChatHistory",
+    "ID INTEGER NOT NULL UNIQUE, MessageID TEXT NOT NULL, Channel TEXT NOT NULL, UserID TEXT NOT NULL, Login TEXT NOT NULL, \
+  DisplayName TEXT NOT NULL, Badges TEXT NOT NULL, Text TEXT NOT NULL, Timestamp INTEGER NOT NULL, Deleted INTEGER NOT NULL DEFAULT 0, \
+  PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Moderation",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL DEFAULT '', Name TEXT NOT NULL, Value TEXT NOT NULL, \
+  PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Quotes",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Number INTEGER NOT NULL, Text TEXT NOT NULL, AddedBy TEXT NOT NULL, \
+  Game TEXT NOT NULL DEFAULT '', Timestamp INTEGER NOT NULL, PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(Channel, Number)",
+  ),
+  (
+    "Counters",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Name TEXT NOT NULL, Value INTEGER NOT NULL DEFAULT 0, \
+  PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(Channel, Name)",
+  ),
+  (
+    "Viewers",
+    "ID INTEGER NOT NULL UNIQUE, UserID TEXT NOT NULL UNIQUE, Login TEXT NOT NULL, DisplayName TEXT NOT NULL, \
+  Points INTEGER NOT NULL DEFAULT 0, Voice TEXT NOT NULL DEFAULT '', PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Templates",
+    "ID INTEGER NOT NULL UNIQUE, Name TEXT NOT NULL UNIQUE, Text TEXT NOT NULL, PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "ScriptData",
+    "ID INTEGER NOT NULL UNIQUE, Key TEXT NOT NULL UNIQUE, Value TEXT NOT NULL, PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Timers",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Name TEXT NOT NULL, Message TEXT NOT NULL, Interval INTEGER NOT NULL, \
+  MinLines INTEGER NOT NULL DEFAULT 0, Enabled INTEGER NOT NULL DEFAULT 1, PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(Channel, Name)",
+  ),
+  (
+    "Raffles",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Keyword TEXT NOT NULL, SubLuck INTEGER NOT NULL DEFAULT 1, \
+  OpenedBy TEXT NOT NULL, Opened INTEGER NOT NULL, Closed INTEGER, PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "RaffleEntries",
+    "ID INTEGER NOT NULL UNIQUE, RaffleID INTEGER NOT NULL, UserID TEXT NOT NULL, Login TEXT NOT NULL, DisplayName TEXT NOT NULL, \
+  Tickets INTEGER NOT NULL DEFAULT 1, Winner INTEGER NOT NULL DEFAULT 0, PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(RaffleID, UserID)",
+  ),
+  (
+    "ModerationLog",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Moderator TEXT NOT NULL, Action TEXT NOT NULL, Target TEXT NOT NULL, \
+  Duration INTEGER, Reason TEXT NOT NULL DEFAULT '', Success INTEGER NOT NULL, Timestamp INTEGER NOT NULL, PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Games",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Name TEXT NOT NULL, Chance INTEGER NOT NULL, Cooldown INTEGER NOT NULL, \
+  PRIMARY KEY(ID AUTOINCREMENT), UNIQUE(Channel, Name)",
+  ),
+  (
+    "GameBets",
+    "ID INTEGER NOT NULL UNIQUE, Channel TEXT NOT NULL, Game TEXT NOT NULL, UserID TEXT NOT NULL, Amount INTEGER NOT NULL, \
+  PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "Emotes",
+    "ID INTEGER NOT NULL UNIQUE, Name TEXT NOT NULL UNIQUE, File TEXT NOT NULL, PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+  (
+    "WalkOns",
+    "ID INTEGER NOT NULL UNIQUE, UserID TEXT NOT NULL UNIQUE, File TEXT NOT NULL, Volume REAL NOT NULL DEFAULT 1, \
+  PRIMARY KEY(ID AUTOINCREMENT)",
+  ),
+];
+/// Indexes created if they are missing in the database (name, table, columns)
+static INDEXES: &[(&str, &str, &str)] = &[
+  ("ChatHistoryMessage", "ChatHistory", "MessageID"),
+  (
+    "ChatHistoryUser",
+    "ChatHistory",
+    "Channel, UserID, Timestamp",
+  ),
+  ("ChatHistoryTime", "ChatHistory", "Channel, Timestamp"),
+  ("ViewersLogin", "Viewers", "Login"),
+];
 
 pub fn init() {
   // Reads the current state of the key from the database or if the key is not found creates it in the database
@@ -46,6 +141,10 @@ pub fn init() {
       log::info!("Connected to database");
     }
   }
+  // Write ahead log lets the threads read while another one is writing, the mode is stored in the database file
+  if let Err(err) = connection.execute("PRAGMA journal_mode=WAL;") {
+    log::warn!("Couldn't enable write ahead log in the database. {}", err);
+  }
 
   // Try to execute some command to check if the table exists
   match connection.execute("SELECT COUNT(*) FROM Config") {
@@ -55,6 +154,22 @@ pub fn init() {
     }
     _ => {}
   }
+  for (name, columns) in TABLES {
+    connection
+      .execute(format!(
+        "CREATE TABLE IF NOT EXISTS {} ({});",
+        name, columns
+      ))
+      .expect("Couldn't create table in the database");
+  }
+  for (name, table, columns) in INDEXES {
+    connection
+      .execute(format!(
+        "CREATE INDEX IF NOT EXISTS {} ON {} ({});",
+        name, table, columns
+      ))
+      .expect("Couldn't create index in the database");
+  }
 
   let mut ok: bool;
   for i in 0..data.len() {
@@ -88,6 +203,127 @@ pub fn init() {
   }
 }
 
+/// Opens new connection to the database. Returns None if the connection couldn't be opened.
+/// The connection waits for other connections writing at the same time instead of failing immediately.
+pub fn connect() -> Option<Connection> {
+  match sqlite::Connection::open(FILE) {
+    Err(err) => {
+      log::error!("Couldn't connect to the database. {}", err);
+      return None;
+    }
+    Ok(mut conn) => {
+      if let Err(err) = conn.set_busy_timeout(BUSY_TIMEOUT_MS) {
+        log::warn!("Couldn't set database busy timeout. {}", err);
+      }
+      return Some(conn);
+    }
+  }
+}
+
+/// Executes provided statement binding `params` to it's '?' placeholders. Returns true on success.
+pub fn execute(query: &str, params: &[Value]) -> bool {
+  return execute_count(query, params).is_some();
+}
+
+/// Executes provided statement binding `params` to it's '?' placeholders.
+/// Returns the number of rows changed by the statement, None if it failed.
+pub fn execute_count(query: &str, params: &[Value]) -> Option<usize> {
+  let conn = connect()?;
+  let res = conn.prepare(query).and_then(|mut statement| {
+    bind(&mut statement, params)?;
+    while statement.next()? == State::Row {}
+    return Ok(());
+  });
+  if let Err(err) = res {
+    log::warn!(
+      "Couldn't execute database query '{}'. Error: {}",
+      query,
+      err
+    );
+    return None;
+  }
+  return Some(conn.change_count());
+}
+
+/// Executes provided statements with their parameters in a single transaction.
+/// Returns false if any of them failed, nothing is changed in that case.
+pub fn transaction(statements: &[(&str, Vec<Value>)]) -> bool {
+  return run_transaction(statements, false);
+}
+
+/// Executes provided statements in a single transaction like `transaction`,
+/// but every statement has to change at least one row, otherwise nothing is changed and false is returned.
+pub fn transaction_all_changed(statements: &[(&str, Vec<Value>)]) -> bool {
+  return run_transaction(statements, true);
+}
+
+fn run_transaction(statements: &[(&str, Vec<Value>)], all_changed: bool) -> bool {
+  let conn = match connect() {
+    Some(conn) => conn,
+    None => return false,
+  };
+  // Immediate transaction takes the write lock at the start, so it waits for other writers instead of failing later
+  let res = conn.execute("BEGIN IMMEDIATE;").and_then(|_| {
+    for (query, params) in statements {
+      let mut statement = conn.prepare(*query)?;
+      bind(&mut statement, params)?;
+      while statement.next()? == State::Row {}
+      if all_changed && conn.change_count() == 0 {
+        return Ok(false);
+      }
+    }
+    conn.execute("COMMIT;")?;
+    return Ok(true);
+  });
+  match res {
+    Ok(true) => return true,
+    Ok(false) => {
+      let _ = conn.execute("ROLLBACK;");
+      return false;
+    }
+    Err(err) => {
+      log::warn!("Couldn't execute database transaction. Error: {}", err);
+      let _ = conn.execute("ROLLBACK;");
+      return false;
+    }
+  }
+}
+
+/// Executes provided query binding `params` to it's '?' placeholders and calls `row` for every returned row.
+/// Returns false if the query failed.
+pub fn query<F>(query: &str, params: &[Value], mut row: F) -> bool
+where
+  F: FnMut(&Statement),
+{
+  let conn = match connect() {
+    Some(conn) => conn,
+    None => return false,
+  };
+  let res = conn.prepare(query).and_then(|mut statement| {
+    bind(&mut statement, params)?;
+    while statement.next()? == State::Row {
+      row(&statement);
+    }
+    return Ok(());
+  });
+  if let Err(err) = res {
+    log::warn!(
+      "Couldn't execute database query '{}'. Error: {}",
+      query,
+      err
+    );
+    return false;
+  }
+  return true;
+}
+
+fn bind(statement: &mut Statement, params: &[Value]) -> sqlite::Result<()> {
+  for (i, param) in params.iter().enumerate() {
+    statement.bind((i + 1, param))?;
+  }
+  return Ok(());
+}
+
 #[allow(dead_code)]
 pub fn get_data(key: Keys) -> String {
   let data = DATA.lock().unwrap();
@@ -102,12 +338,9 @@ pub fn get_data(key: Keys) -> String {
 
 #[allow(dead_code)]
 pub fn update_value(key: Keys, value: String) {
-  match sqlite::Connection::open(FILE) {
-    Err(err) => {
-      log::error!("{}", err);
-      return;
-    }
-    Ok(conn) => {
+  match connect() {
+    None => return,
+    Some(conn) => {
       match (conn as Connection).execute(format!(
         "UPDATE Config SET Value='{}' WHERE Name='{:?}';",
         value, key
@@ -2,7 +2,7 @@ use serde_json::json;
 use std::{thread, time::Duration};
 use tungstenite::{client::IntoClientRequest, Message};
 
-use crate::{database, notifications, secrets};
+use crate::{chat, database, helix, notifications, scripts, secrets, tts};
 
 // const WEBSOCKETURL: &str = "wss://eventsub.wss.twitch.tv/ws";
 // const SUBSCRIPTIONURL: &str = "https://api.twitch.tv/helix/eventsub/subscriptions";
@@ -93,10 +93,61 @@ fn update() {
                   // Channel follow
                   println!(">> New follow from {}.", user_name);
                   notifications::add_follow_notification(user_name);
+                  scripts::handle_event(
+                    scripts::Event::Follow,
+                    &secrets::get_data(secrets::Keys::Channel),
+                    chat::Permission::Everyone,
+                    scripts::user_context(user_name),
+                  );
                 } else if msg["payload"]["subscription"]["type"] == "channel.subscribe" {
                   // Channel subscription
                   println!(">> New sub from {}.", user_name);
                   notifications::add_subscription_notification(user_name);
+                  scripts::handle_event(
+                    scripts::Event::Subscription,
+                    &secrets::get_data(secrets::Keys::Channel),
+                    chat::Permission::Everyone,
+                    scripts::user_context(user_name),
+                  );
+                } else if msg["payload"]["subscription"]["type"]
+                  == "channel.channel_points_custom_reward_redemption.add"
+                {
+                  // Channel points redemption
+                  let event = &msg["payload"]["event"];
+                  let title = event["reward"]["title"].as_str().unwrap_or_default();
+                  println!(">> {} redeemed {}.", user_name, title);
+                  if title.eq_ignore_ascii_case(tts::REWARD_TITLE) {
+                    tts::redeemed(
+                      event["user_id"].as_str().unwrap_or_default(),
+                      user_name,
+                      event["user_input"].as_str().unwrap_or_default(),
+                    );
+                  }
+                } else if msg["payload"]["subscription"]["type"] == "channel.raid" {
+                  // Channel got raided
+                  let event = &msg["payload"]["event"];
+                  println!(
+                    ">> Raid from {}.",
+                    event["from_broadcaster_user_name"]
+                      .as_str()
+                      .unwrap_or_default()
+                  );
+                  chat::shoutout::raided(
+                    &secrets::get_data(secrets::Keys::Channel),
+                    event["from_broadcaster_user_login"]
+                      .as_str()
+                      .unwrap_or_default(),
+                    event["viewers"].as_i64().unwrap_or_default(),
+                  );
+                } else if msg["payload"]["subscription"]["type"] == "user.whisper.message" {
+                  // Whisper received by the bot
+                  let event = &msg["payload"]["event"];
+                  chat::handle_whisper(
+                    event["from_user_id"].as_str().unwrap_or_default(),
+                    event["from_user_login"].as_str().unwrap_or_default(),
+                    event["from_user_name"].as_str().unwrap_or_default(),
+                    event["whisper"]["text"].as_str().unwrap_or_default(),
+                  );
                 } else {
                   // Unrecognized notification
                   println!("{}", msg);
@@ -165,6 +216,24 @@ fn subscribe_to_events(twitch_id: &str, twitch_oauth: &str, session_id: &str) ->
     twitch_id,
     twitch_oauth,
   ); // A Hype Train makes progress on the specified channel
+  any_sub_succeeded |= subscribe_with_condition(
+    "channel.raid",
+    "1",
+    json!({ "to_broadcaster_user_id": secrets::get_data(secrets::Keys::ChannelID) }),
+    session_id,
+    twitch_id,
+    twitch_oauth,
+  ); // Channel got raided
+  if let Some(user_id) = helix::token_user_id() {
+    any_sub_succeeded |= subscribe_with_condition(
+      "user.whisper.message",
+      "1",
+      json!({ "user_id": user_id }),
+      session_id,
+      twitch_id,
+      twitch_oauth,
+    ); // The bot received a whisper
+  }
 
   return !any_sub_succeeded;
 }
@@ -175,17 +244,35 @@ fn subscribe(
   session_id: &str,
   twitch_id: &str,
   twitch_oauth: &str,
+) -> bool {
+  let channel_id = secrets::get_data(secrets::Keys::ChannelID);
+  return subscribe_with_condition(
+    sub_type,
+    version,
+    json!({
+      "broadcaster_user_id": &channel_id,
+      "moderator_user_id": &channel_id
+    }),
+    session_id,
+    twitch_id,
+    twitch_oauth,
+  );
+}
+
+fn subscribe_with_condition(
+  sub_type: &str,
+  version: &str,
+  condition: serde_json::Value,
+  session_id: &str,
+  twitch_id: &str,
+  twitch_oauth: &str,
 ) -> bool {
   log::info!("Events bot subscribing to {sub_type} event.");
 
-  let channel_id = secrets::get_data(secrets::Keys::ChannelID);
   let content = json!({
     "type": sub_type,
     "version": version,
-    "condition": {
-      "broadcaster_user_id": &channel_id,
-      "moderator_user_id": &channel_id
-    },
+    "condition": condition,
     "transport": {
       "method": "websocket",
       "session_id": session_id
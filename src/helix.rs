@@ -0,0 +1,346 @@
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::{access_tokens, database, secrets};
+
+/// Twitch API address
+const HELIX_URL: &str = "https://api.twitch.tv/helix";
+
+/// ID of the user the access token belongs to, used as moderator ID in moderation requests
+static TOKEN_USER_ID: Mutex<String> = Mutex::new(String::new());
+
+/// Sends request to Twitch API. `path` contains the endpoint with query parameters, for example "/users?login=abc".
+/// If the access token expired it is refreshed and the request is repeated.
+/// Returns parsed json response, for responses without content returns Value::Null.
+fn request(method: &str, path: &str, body: Option<&Value>) -> Result<Value, String> {
+  let mut refreshed = false;
+  loop {
+    let req = ureq::request(method, &format!("{}{}", HELIX_URL, path))
+      .set(
+        "Authorization",
+        &format!("Bearer {}", database::get_data(database::Keys::TwitchOAuth)),
+      )
+      .set("Client-Id", &secrets::get_data(secrets::Keys::TwitchID));
+    let response = match body {
+      Some(body) => req
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string()),
+      None => req.call(),
+    };
+
+    match response {
+      Ok(resp) => {
+        let text = resp.into_string().map_err(|e| e.to_string())?;
+        if text.len() == 0 {
+          return Ok(Value::Null);
+        }
+        return serde_json::from_str(&text).map_err(|e| e.to_string());
+      }
+      Err(ureq::Error::Status(401, _)) if !refreshed => {
+        refreshed = true;
+        if !access_tokens::refresh_twitch() {
+          return Err("access token expired and couldn't be refreshed".to_string());
+        }
+      }
+      Err(ureq::Error::Status(code, resp)) => {
+        let text = resp.into_string().unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&text)
+          .ok()
+          .and_then(|v| v["message"].as_str().map(|m| m.to_string()))
+          .unwrap_or(text);
+        return Err(format!("{} {}", code, message));
+      }
+      Err(err) => return Err(err.to_string()),
+    }
+  }
+}
+
+/// Returns the ID of the user the access token belongs to
+pub fn token_user_id() -> Option<String> {
+  let mut id = TOKEN_USER_ID.lock().unwrap();
+  if id.len() == 0 {
+    match request("GET", "/users", None) {
+      Ok(resp) => {
+        id.push_str(resp["data"][0]["id"].as_str().unwrap_or_default());
+      }
+      Err(err) => {
+        log::error!("Couldn't get access token user ID. {}", err);
+      }
+    }
+  }
+  if id.len() == 0 {
+    return None;
+  }
+  return Some(id.clone());
+}
+
+/// Deletes chat message. Returns true on success.
+pub fn delete_message(broadcaster_id: &str, message_id: &str) -> bool {
+  let moderator_id = match token_user_id() {
+    Some(id) => id,
+    None => return false,
+  };
+  match request(
+    "DELETE",
+    &format!(
+      "/moderation/chat?broadcaster_id={}&moderator_id={}&message_id={}",
+      broadcaster_id, moderator_id, message_id
+    ),
+    None,
+  ) {
+    Ok(_) => return true,
+    Err(err) => {
+      log::warn!("Couldn't delete chat message. {}", err);
+      return false;
+    }
+  }
+}
+
+/// Bans the user, if `duration` (in seconds) is provided the user is timed out instead. Returns true on success.
+pub fn ban_user(broadcaster_id: &str, user_id: &str, duration: Option<u32>, reason: &str) -> bool {
+  let moderator_id = match token_user_id() {
+    Some(id) => id,
+    None => return false,
+  };
+  let mut data = json!({
+    "user_id": user_id,
+    "reason": reason,
+  });
+  if let Some(duration) = duration {
+    data["duration"] = json!(duration);
+  }
+  match request(
+    "POST",
+    &format!(
+      "/moderation/bans?broadcaster_id={}&moderator_id={}",
+      broadcaster_id, moderator_id
+    ),
+    Some(&json!({ "data": data })),
+  ) {
+    Ok(_) => return true,
+    Err(err) => {
+      log::warn!("Couldn't ban the user. {}", err);
+      return false;
+    }
+  }
+}
+
+/// Live stream data
+pub struct Stream {
+  pub user_login: String,
+  pub game_name: String,
+  pub title: String,
+  /// RFC3339 time the stream started
+  pub started_at: String,
+}
+
+/// Returns live streams of provided channels (logins), channels that are offline are not included
+pub fn get_streams(logins: &[String]) -> Option<Vec<Stream>> {
+  let query = logins
+    .iter()
+    .map(|l| format!("user_login={}", l))
+    .collect::<Vec<String>>()
+    .join("&");
+  match request("GET", &format!("/streams?{}", query), None) {
+    Ok(resp) => {
+      let mut streams = Vec::new();
+      for s in resp["data"].as_array().unwrap_or(&Vec::new()) {
+        streams.push(Stream {
+          user_login: s["user_login"].as_str().unwrap_or_default().to_string(),
+          game_name: s["game_name"].as_str().unwrap_or_default().to_string(),
+          title: s["title"].as_str().unwrap_or_default().to_string(),
+          started_at: s["started_at"].as_str().unwrap_or_default().to_string(),
+        });
+      }
+      return Some(streams);
+    }
+    Err(err) => {
+      log::warn!("Couldn't get stream status. {}", err);
+      return None;
+    }
+  }
+}
+
+/// Channel information, available also when the channel is offline
+pub struct ChannelInfo {
+  pub game_name: String,
+  pub title: String,
+}
+
+/// Returns information about the channel with provided ID
+pub fn get_channel_info(broadcaster_id: &str) -> Option<ChannelInfo> {
+  match request(
+    "GET",
+    &format!("/channels?broadcaster_id={}", broadcaster_id),
+    None,
+  ) {
+    Ok(resp) => {
+      let c = &resp["data"][0];
+      if c.is_null() {
+        return None;
+      }
+      return Some(ChannelInfo {
+        game_name: c["game_name"].as_str().unwrap_or_default().to_string(),
+        title: c["title"].as_str().unwrap_or_default().to_string(),
+      });
+    }
+    Err(err) => {
+      log::warn!("Couldn't get channel information. {}", err);
+      return None;
+    }
+  }
+}
+
+/// User connected to the chat
+pub struct Chatter {
+  pub user_id: String,
+  pub login: String,
+  pub display_name: String,
+}
+
+/// Returns users connected to the chat of the channel
+pub fn get_chatters(broadcaster_id: &str) -> Option<Vec<Chatter>> {
+  let moderator_id = token_user_id()?;
+  let mut chatters = Vec::new();
+  let mut cursor = String::new();
+  loop {
+    let mut path = format!(
+      "/chat/chatters?broadcaster_id={}&moderator_id={}&first=1000",
+      broadcaster_id, moderator_id
+    );
+    if cursor.len() > 0 {
+      path.push_str(&format!("&after={}", cursor));
+    }
+    let resp = match request("GET", &path, None) {
+      Ok(resp) => resp,
+      Err(err) => {
+        log::warn!("Couldn't get chatters. {}", err);
+        return None;
+      }
+    };
+    for c in resp["data"].as_array().unwrap_or(&Vec::new()) {
+      chatters.push(Chatter {
+        user_id: c["user_id"].as_str().unwrap_or_default().to_string(),
+        login: c["user_login"].as_str().unwrap_or_default().to_string(),
+        display_name: c["user_name"].as_str().unwrap_or_default().to_string(),
+      });
+    }
+    cursor = resp["pagination"]["cursor"]
+      .as_str()
+      .unwrap_or_default()
+      .to_string();
+    if cursor.len() == 0 {
+      return Some(chatters);
+    }
+  }
+}
+
+/// Returns the ID of the user with provided login
+pub fn get_user_id(login: &str) -> Option<String> {
+  match request(
+    "GET",
+    &format!(
+      "/users?login={}",
+      login.trim_start_matches('@').to_lowercase()
+    ),
+    None,
+  ) {
+    Ok(resp) => return resp["data"][0]["id"].as_str().map(|id| id.to_string()),
+    Err(err) => {
+      log::warn!("Couldn't get user ID of {}. {}", login, err);
+      return None;
+    }
+  }
+}
+
+/// Returns RFC3339 time the user followed the channel, None if the user is not following it
+pub fn get_followed_at(broadcaster_id: &str, user_id: &str) -> Option<String> {
+  match request(
+    "GET",
+    &format!(
+      "/channels/followers?broadcaster_id={}&user_id={}",
+      broadcaster_id, user_id
+    ),
+    None,
+  ) {
+    Ok(resp) => {
+      return resp["data"][0]["followed_at"]
+        .as_str()
+        .map(|t| t.to_string())
+    }
+    Err(err) => {
+      log::warn!("Couldn't get follow information. {}", err);
+      return None;
+    }
+  }
+}
+
+/// Sends whisper from the access token user to provided user. Returns true on success.
+pub fn send_whisper(to_user_id: &str, message: &str) -> bool {
+  let from_user_id = match token_user_id() {
+    Some(id) => id,
+    None => return false,
+  };
+  // Whispers to users the bot didn't whisper before are limited to 500 characters
+  let message: String = message.chars().take(500).collect();
+  match request(
+    "POST",
+    &format!(
+      "/whispers?from_user_id={}&to_user_id={}",
+      from_user_id, to_user_id
+    ),
+    Some(&json!({ "message": message })),
+  ) {
+    Ok(_) => return true,
+    Err(err) => {
+      log::warn!("Couldn't send whisper. {}", err);
+      return false;
+    }
+  }
+}
+
+/// Removes the ban or timeout of the user. Returns true on success.
+pub fn unban_user(broadcaster_id: &str, user_id: &str) -> bool {
+  let moderator_id = match token_user_id() {
+    Some(id) => id,
+    None => return false,
+  };
+  match request(
+    "DELETE",
+    &format!(
+      "/moderation/bans?broadcaster_id={}&moderator_id={}&user_id={}",
+      broadcaster_id, moderator_id, user_id
+    ),
+    None,
+  ) {
+    Ok(_) => return true,
+    Err(err) => {
+      log::warn!("Couldn't unban the user. {}", err);
+      return false;
+    }
+  }
+}
+
+/// Sends native shoutout of the other channel. Twitch allows one shoutout every 2 minutes
+/// and one shoutout of the same channel every 60 minutes, only while the stream is live.
+pub fn send_shoutout(broadcaster_id: &str, to_broadcaster_id: &str) -> bool {
+  let moderator_id = match token_user_id() {
+    Some(id) => id,
+    None => return false,
+  };
+  match request(
+    "POST",
+    &format!(
+      "/chat/shoutouts?from_broadcaster_id={}&to_broadcaster_id={}&moderator_id={}",
+      broadcaster_id, to_broadcaster_id, moderator_id
+    ),
+    None,
+  ) {
+    Ok(_) => return true,
+    Err(err) => {
+      log::warn!("Couldn't send the shoutout. {}", err);
+      return false;
+    }
+  }
+}
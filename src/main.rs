@@ -5,8 +5,14 @@ mod chat;
 mod client;
 mod database;
 mod events;
+mod helix;
 mod notifications;
+mod points;
+mod scripts;
 mod secrets;
+mod stream;
+mod template;
+mod tts;
 
 fn main() {
   // Logger setup
@@ -36,11 +42,16 @@ fn main() {
     return;
   }
 
+  // Custom commands and counters are loaded by the chat first, so the scripts can check their names
   chat::start();
+  scripts::load();
   events::start();
+  stream::start();
+  points::start();
 
   client::start();
   notifications::start();
+  tts::start();
 
   // Main loop?
   let sleep_dur = Duration::from_millis(10);
@@ -2,7 +2,7 @@ use std::{collections::VecDeque, sync::Mutex, thread, time::Duration};
 
 use serde_json::json;
 
-use crate::{chat, client};
+use crate::{chat, client, template, tts};
 
 #[derive(Copy, Clone)]
 enum NotificationType {
@@ -29,6 +29,7 @@ struct Notification {
   message_displayed: Option<String>,
   message_displayed_position: (i32, i32),
   message_read: Option<String>, // TTS
+  message_read_user_id: String, // user whose voice reads the message, default voice if empty
   played_sound: Option<String>, // name of the sound that server would be asked to provide
   played_sound_volume: f32,
   played_video: Option<String>, // name of the video that server would be asked to provide
@@ -45,6 +46,7 @@ impl Default for Notification {
       message_displayed: None,
       message_displayed_position: (0, 0),
       message_read: None,
+      message_read_user_id: String::new(),
       played_sound: None,
       played_sound_volume: 1.0,
       played_video: None,
@@ -63,6 +65,11 @@ impl Notification {
       chat::send_message(&msg);
     }
 
+    let message_read = self
+      .message_read
+      .as_ref()
+      .and_then(|text| tts::render(text, &self.message_read_user_id));
+
     // FIXME: missing data to be sent to the client
     return client::send_text_message(
       &json!({
@@ -71,6 +78,8 @@ impl Notification {
         "message_displayed": self.message_displayed,
         "message_displayed_position": self.message_displayed_position,
 
+        "message_read": message_read,
+
         "played_sound": self.played_sound,
         "played_sound_volume": self.played_sound_volume,
 
@@ -125,10 +134,11 @@ fn update() {
 
   loop {
     if !started {
-      let mut queue = QUEUE.lock().unwrap();
+      // The queue is released before starting, text to speech rendering can take a while
+      let next = QUEUE.lock().unwrap().pop_front();
 
-      if queue.len() > 0 {
-        current_notificaiton = queue.pop_front().unwrap();
+      if let Some(next) = next {
+        current_notificaiton = next;
         if current_notificaiton.start() {
           // .start() returned true - there are some clients playing notificaiton
           started = true;
@@ -153,11 +163,18 @@ fn update() {
 }
 
 pub fn add_follow_notification(user_name: &str) {
+  let ctx = template::Context::from_user(user_name);
   let mut queue = QUEUE.lock().unwrap();
   let notification = Notification {
     thetype: NotificationType::FOLLOW,
-    message_chat: Some(format!("@{} thank you for following!", user_name)),
-    message_displayed: Some(format!("New follower {}!", user_name)),
+    message_chat: Some(template::render(
+      &template::get("FollowChat", "@$(user) thank you for following!"),
+      &ctx,
+    )),
+    message_displayed: Some(template::render(
+      &template::get("FollowDisplayed", "New follower $(user)!"),
+      &ctx,
+    )),
     message_displayed_position: (100, 200),
     played_sound: Some("follow_sound".to_string()),
     played_sound_volume: 0.2,
@@ -167,10 +184,14 @@ pub fn add_follow_notification(user_name: &str) {
 }
 
 pub fn add_subscription_notification(user_name: &str) {
+  let ctx = template::Context::from_user(user_name);
   let mut queue = QUEUE.lock().unwrap();
   let notification = Notification {
     thetype: NotificationType::SUBSCRIPTION,
-    message_displayed: Some(format!("{} just subscribed!", user_name)),
+    message_displayed: Some(template::render(
+      &template::get("SubscriptionDisplayed", "$(user) just subscribed!"),
+      &ctx,
+    )),
     message_displayed_position: (100, 200),
     played_video: Some("sub_video".to_string()),
     played_video_volume: 0.5,
@@ -181,6 +202,50 @@ pub fn add_subscription_notification(user_name: &str) {
   queue.push_back(notification);
 }
 
+/// Adds notification only displaying provided text
+pub fn add_text_notification(text: &str) {
+  let mut queue = QUEUE.lock().unwrap();
+  let notification = Notification {
+    message_displayed: Some(text.to_string()),
+    message_displayed_position: (100, 200),
+    ..Default::default()
+  };
+  queue.push_back(notification);
+}
+
+/// Adds greeting of the chatter, every part of it is optional
+pub fn add_greeting_notification(
+  message_chat: Option<String>,
+  message_displayed: Option<String>,
+  walkon: Option<(String, f32)>,
+) {
+  let mut queue = QUEUE.lock().unwrap();
+  let (played_sound, played_sound_volume) = match walkon {
+    Some((sound, volume)) => (Some(sound), volume),
+    None => (None, 1.0),
+  };
+  let notification = Notification {
+    message_chat,
+    message_displayed,
+    message_displayed_position: (100, 200),
+    played_sound,
+    played_sound_volume,
+    ..Default::default()
+  };
+  queue.push_back(notification);
+}
+
+/// Adds notification reading provided text with the voice of the user
+pub fn add_tts_notification(user_id: &str, text: &str) {
+  let mut queue = QUEUE.lock().unwrap();
+  let notification = Notification {
+    message_read: Some(text.to_string()),
+    message_read_user_id: user_id.to_string(),
+    ..Default::default()
+  };
+  queue.push_back(notification);
+}
+
 pub fn add_subscription_ext_notification() {
   let mut queue = QUEUE.lock().unwrap();
   let notification = Notification {
@@ -0,0 +1,311 @@
+use std::{sync::Mutex, thread, time::Duration};
+
+use crate::{
+  chat::{self, Permission, Privmsg},
+  database, helix, stream,
+};
+
+/// Viewer stored in the database
+pub struct Viewer {
+  pub user_id: String,
+  pub display_name: String,
+  pub points: i64,
+}
+
+/// User that chatted during current points interval
+struct ActiveChatter {
+  channel: String,
+  user_id: String,
+}
+
+/// Points given to a viewer in one payout
+struct Payout {
+  user_id: String,
+  /// Login and display name, if known from the chatters list
+  names: Option<(String, String)>,
+  points: i64,
+}
+
+/// Users that chatted since the last points payout
+static ACTIVE: Mutex<Vec<ActiveChatter>> = Mutex::new(Vec::new());
+/// Interval of giving points to the viewers while the stream is live
+const INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Points given to every viewer each interval
+const POINTS_PER_INTERVAL: i64 = 10;
+/// Additional points given to viewers that chatted during the interval
+const CHAT_BONUS: i64 = 5;
+/// Adds the viewer or updates their names, parameters: user ID, login, display name
+const UPDATE_VIEWER: &str = "INSERT INTO Viewers (UserID, Login, DisplayName) VALUES (?, ?, ?) \
+  ON CONFLICT(UserID) DO UPDATE SET Login = excluded.Login, DisplayName = excluded.DisplayName;";
+/// Adds points to the viewer, staying between 0 and maximum, parameters: points, maximum, user ID
+const ADD_POINTS: &str = "UPDATE Viewers SET Points = MIN(MAX(Points + ?, 0), ?) WHERE UserID = ?;";
+/// Maximum number of points of the viewer, keeps the points far from integer overflow
+const MAX_POINTS: i64 = 1_000_000_000_000_000;
+/// Takes points from the viewer if they have enough, parameters: points, user ID, points
+const SPEND_POINTS: &str =
+  "UPDATE Viewers SET Points = Points - ? WHERE UserID = ? AND Points >= ?;";
+
+pub fn start() {
+  // Create points thread
+  thread::Builder::new()
+    .name("Points".to_string())
+    .spawn(move || loop {
+      thread::sleep(INTERVAL);
+      payout();
+    })
+    .expect("Spawning points thread failed!");
+}
+
+/// Gives points to viewers of live channels.
+/// Viewers watching multiple channels are paid only once, all changes are stored in one transaction.
+fn payout() {
+  let mut payouts: Vec<Payout> = Vec::new();
+  for channel in chat::channels() {
+    let active: Vec<String> = {
+      let mut active = ACTIVE.lock().unwrap();
+      let users = active
+        .iter()
+        .filter(|a| a.channel == channel)
+        .map(|a| a.user_id.clone())
+        .collect();
+      active.retain(|a| a.channel != channel);
+      users
+    };
+    if !stream::is_online(&channel) {
+      continue;
+    }
+
+    let chatters = chat::room_id(&channel)
+      .and_then(|id| helix::get_chatters(&id))
+      .unwrap_or_default();
+    for c in chatters.iter() {
+      if !payouts.iter().any(|p| p.user_id == c.user_id) {
+        payouts.push(Payout {
+          user_id: c.user_id.clone(),
+          names: Some((c.login.clone(), c.display_name.clone())),
+          points: POINTS_PER_INTERVAL,
+        });
+      }
+    }
+    for user_id in active.iter() {
+      match payouts.iter_mut().find(|p| p.user_id == *user_id) {
+        // Chat bonus is given once, even if the user chatted in multiple channels
+        Some(p) => p.points = POINTS_PER_INTERVAL + CHAT_BONUS,
+        None => payouts.push(Payout {
+          user_id: user_id.clone(),
+          names: None,
+          points: POINTS_PER_INTERVAL + CHAT_BONUS,
+        }),
+      }
+    }
+    log::info!(
+      "Points given to {} viewers and {} chatters in #{}",
+      chatters.len(),
+      active.len(),
+      channel
+    );
+  }
+  if payouts.len() == 0 {
+    return;
+  }
+
+  let mut statements = Vec::new();
+  for payout in payouts {
+    if let Some((login, display_name)) = payout.names {
+      statements.push((
+        UPDATE_VIEWER,
+        vec![
+          payout.user_id.as_str().into(),
+          login.into(),
+          display_name.into(),
+        ],
+      ));
+    }
+    statements.push((
+      ADD_POINTS,
+      vec![
+        payout.points.into(),
+        MAX_POINTS.into(),
+        payout.user_id.into(),
+      ],
+    ));
+  }
+  if !database::transaction(&statements) {
+    log::error!("Couldn't store points payout");
+  }
+}
+
+/// Marks the author of the chat message as active, giving them chat bonus on next payout
+pub fn chatted(msg: &Privmsg) {
+  if msg.user_id.len() == 0 || !stream::is_online(&msg.channel) {
+    return;
+  }
+  {
+    let mut active = ACTIVE.lock().unwrap();
+    if active
+      .iter()
+      .any(|a| a.channel == msg.channel && a.user_id == msg.user_id)
+    {
+      return;
+    }
+    active.push(ActiveChatter {
+      channel: msg.channel.clone(),
+      user_id: msg.user_id.clone(),
+    });
+  }
+  update_viewer(&msg.user_id, &msg.login, &msg.display_name);
+}
+
+/// Adds the viewer to the database or updates their names
+pub fn update_viewer(user_id: &str, login: &str, display_name: &str) {
+  database::execute(
+    UPDATE_VIEWER,
+    &[user_id.into(), login.into(), display_name.into()],
+  );
+}
+
+/// Finds the viewer by user ID, login or display name (with optional '@')
+pub fn find_viewer(user: &str) -> Option<Viewer> {
+  let user = user.trim_start_matches('@');
+  let mut viewer = None;
+  database::query(
+    "SELECT UserID, DisplayName, Points FROM Viewers WHERE UserID = ? OR Login = ? OR DisplayName = ? COLLATE NOCASE LIMIT 1;",
+    &[user.into(), user.to_lowercase().into(), user.into()],
+    |row| {
+      viewer = Some(Viewer {
+        user_id: row.read::<String, _>(0).unwrap_or_default(),
+        display_name: row.read::<String, _>(1).unwrap_or_default(),
+        points: row.read::<i64, _>(2).unwrap_or_default(),
+      });
+    },
+  );
+  return viewer;
+}
+
+/// Returns points of the viewer
+pub fn get(user_id: &str) -> i64 {
+  let mut points = 0;
+  database::query(
+    "SELECT Points FROM Viewers WHERE UserID = ?;",
+    &[user_id.into()],
+    |row| points = row.read::<i64, _>(0).unwrap_or_default(),
+  );
+  return points;
+}
+
+/// Adds points to the viewer (negative `amount` removes them, but never below 0).
+/// Returns false if the viewer doesn't exist.
+pub fn add(user_id: &str, amount: i64) -> bool {
+  let amount = amount.clamp(-MAX_POINTS, MAX_POINTS);
+  return database::execute_count(
+    ADD_POINTS,
+    &[amount.into(), MAX_POINTS.into(), user_id.into()],
+  )
+  .unwrap_or_default()
+    > 0;
+}
+
+/// Takes points from the viewer. Returns false if the viewer doesn't have enough points.
+pub fn spend(user_id: &str, amount: i64) -> bool {
+  if amount < 0 {
+    return false;
+  }
+  return database::execute_count(
+    SPEND_POINTS,
+    &[amount.into(), user_id.into(), amount.into()],
+  )
+  .unwrap_or_default()
+    > 0;
+}
+
+/// Handles "!points", "!give" and "!addpoints" commands
+pub fn handle_command(msg: &Privmsg, command: &str, args: &str) {
+  let words: Vec<&str> = args.split_whitespace().collect();
+  let answer = match command {
+    "!points" => {
+      if !chat::check_access(msg, "points", Permission::Everyone, 0, 10) {
+        return;
+      }
+      match words.first() {
+        Some(user) => match find_viewer(user) {
+          Some(v) => format!("{} has {} points", v.display_name, v.points),
+          None => format!(
+            "{} doesn't have any points yet",
+            user.trim_start_matches('@')
+          ),
+        },
+        None => format!("You have {} points", get(&msg.user_id)),
+      }
+    }
+    "!give" => {
+      let (target, amount) = match parse_target(&words, "!give") {
+        Ok(v) => v,
+        Err(err) => {
+          chat::reply(msg, &err);
+          return;
+        }
+      };
+      if amount <= 0 || target.user_id == msg.user_id {
+        return;
+      }
+      // Both changes are stored together, so the points can't get lost between them
+      if database::transaction_all_changed(&[
+        (
+          SPEND_POINTS,
+          vec![amount.into(), msg.user_id.as_str().into(), amount.into()],
+        ),
+        (
+          ADD_POINTS,
+          vec![
+            amount.into(),
+            MAX_POINTS.into(),
+            target.user_id.as_str().into(),
+          ],
+        ),
+      ]) {
+        format!("You gave {} points to {}", amount, target.display_name)
+      } else {
+        "You don't have enough points".to_string()
+      }
+    }
+    "!addpoints" => {
+      if msg.permission() < Permission::Moderator {
+        return;
+      }
+      match parse_target(&words, "!addpoints") {
+        Ok((target, amount)) => {
+          add(&target.user_id, amount);
+          format!(
+            "{} now has {} points",
+            target.display_name,
+            get(&target.user_id)
+          )
+        }
+        Err(err) => err,
+      }
+    }
+    _ => return,
+  };
+  chat::reply(msg, &answer);
+}
+
+/// Parses "<user> <amount>" arguments of the command, the user has to be known viewer.
+/// Returns the message for the chat on error.
+fn parse_target(words: &[&str], command: &str) -> Result<(Viewer, i64), String> {
+  let (user, amount) = match (
+    words.first(),
+    words.get(1).and_then(|a| a.parse::<i64>().ok()),
+  ) {
+    (Some(user), Some(amount)) => (user, amount),
+    _ => return Err(format!("Usage: {} <user> <amount>", command)),
+  };
+  match find_viewer(user) {
+    Some(viewer) => return Ok((viewer, amount)),
+    None => {
+      return Err(format!(
+        "{} doesn't have any points yet",
+        user.trim_start_matches('@')
+      ))
+    }
+  }
+}
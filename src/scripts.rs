@@ -0,0 +1,350 @@
+use std::{
+  cell::RefCell,
+  fs,
+  sync::{Mutex, OnceLock},
+  thread,
+};
+
+use rand::Rng;
+use rhai::{module_resolvers::DummyModuleResolver, Dynamic, Engine, Map, Scope, AST};
+
+use crate::{
+  chat::{self, counters, mod_commands, Permission, Priority, Privmsg},
+  database, notifications, secrets,
+};
+
+/// Script loaded from the scripts directory
+struct Script {
+  /// Name of the file without extension, used as chat command name
+  name: String,
+  ast: AST,
+}
+
+/// Event handlers that can be defined by the scripts
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+  /// Chat message, called with user, user_id, channel and text
+  Message,
+  /// Channel follow, called with user
+  Follow,
+  /// Channel subscription, called with user
+  Subscription,
+}
+
+impl Event {
+  fn function(&self) -> &'static str {
+    match self {
+      Event::Message => "on_message",
+      Event::Follow => "on_follow",
+      Event::Subscription => "on_subscription",
+    }
+  }
+}
+
+/// Loaded scripts
+static SCRIPTS: Mutex<Vec<Script>> = Mutex::new(Vec::new());
+/// Scripting engine shared by all script calls
+static ENGINE: OnceLock<Engine> = OnceLock::new();
+
+thread_local! {
+  /// Channel and permission of the script call running on this thread, used by the functions registered in the engine
+  static CALL: RefCell<(String, Permission)> = const { RefCell::new((String::new(), Permission::Everyone)) };
+}
+/// Directory the scripts are loaded from
+const SCRIPTS_DIR: &str = "scripts";
+/// Name of the function called when the script is used as chat command
+const COMMAND_FUNCTION: &str = "on_command";
+/// Maximum number of operations of single script call, stops infinite loops
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Loads scripts (*.rhai files) from the scripts directory.
+/// Script defining `on_command(ctx)` function is available as chat command named like the file,
+/// scripts can also define `on_message(ctx)`, `on_follow(ctx)` and `on_subscription(ctx)` event handlers.
+pub fn load() {
+  let mut scripts = SCRIPTS.lock().unwrap();
+  scripts.clear();
+  let entries = match fs::read_dir(SCRIPTS_DIR) {
+    Ok(entries) => entries,
+    Err(_) => {
+      log::info!("No {} directory, scripts are not loaded", SCRIPTS_DIR);
+      return;
+    }
+  };
+
+  let engine = ENGINE.get_or_init(engine);
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.extension().map(|e| e != "rhai").unwrap_or(true) {
+      continue;
+    }
+    let name = path
+      .file_stem()
+      .unwrap_or_default()
+      .to_string_lossy()
+      .to_lowercase();
+    let source = match fs::read_to_string(&path) {
+      Ok(source) => source,
+      Err(err) => {
+        log::warn!("Couldn't read script {}. {}", path.display(), err);
+        continue;
+      }
+    };
+    match engine.compile(&source) {
+      Ok(ast) => {
+        if ast.iter_functions().any(|f| f.name == COMMAND_FUNCTION) {
+          if chat::is_builtin(&name) {
+            log::warn!(
+              "Script {} can't be used as a command, !{} is a built-in command",
+              name,
+              name
+            );
+          } else if chat::channels()
+            .iter()
+            .any(|c| chat::command_exists(c, &name) || counters::exists(c, &name))
+          {
+            log::warn!(
+              "Script {} hides custom command or counter !{}, rename one of them",
+              name,
+              name
+            );
+          }
+        }
+        scripts.push(Script { name, ast });
+      }
+      Err(err) => log::warn!("Couldn't compile script {}. {}", path.display(), err),
+    }
+  }
+  log::info!("Loaded {} scripts", scripts.len());
+}
+
+/// Returns the channel of the current script call
+fn channel() -> String {
+  return CALL.with(|c| c.borrow().0.clone());
+}
+
+/// Starts moderation action of the script on a separate thread, so Twitch API requests don't hold up the chat.
+/// The script call has to be started by a moderator, returns false if it wasn't.
+fn moderate<F>(user: &str, reason: &str, action: F) -> bool
+where
+  F: FnOnce(&str, &str, &str) -> Result<(), String> + Send + 'static,
+{
+  let (channel, permission) = CALL.with(|c| c.borrow().clone());
+  if permission < Permission::Moderator {
+    log::warn!("Script moderation action was not started by a moderator, skipped");
+    return false;
+  }
+  let user = user.to_string();
+  let reason = reason.to_string();
+  thread::spawn(move || {
+    if let Err(err) = action(&channel, &user, &reason) {
+      log::warn!("Script moderation action failed. {}", err);
+    }
+  });
+  return true;
+}
+
+/// Creates scripting engine with sandboxed API, messages sent by the scripts go to the channel of the call.
+/// Scripts can't import modules or access files.
+fn engine() -> Engine {
+  let mut engine = Engine::new();
+  engine.set_module_resolver(DummyModuleResolver::new());
+  engine.set_max_operations(MAX_OPERATIONS);
+  engine.set_max_call_levels(32);
+  engine.set_max_expr_depths(64, 32);
+  engine.set_max_string_size(10_000);
+  engine.set_max_array_size(1_000);
+  engine.set_max_map_size(1_000);
+  engine.on_print(|text| log::info!("Script: {}", text));
+  engine.on_debug(|text, _, pos| log::debug!("Script {:?}: {}", pos, text));
+
+  engine.register_fn("send_message", |text: &str| {
+    chat::send_message_to(&channel(), text, Priority::Normal);
+  });
+  engine.register_fn("db_get", |key: &str| -> String {
+    let mut value = String::new();
+    database::query(
+      "SELECT Value FROM ScriptData WHERE Key = ?;",
+      &[key.into()],
+      |row| value = row.read::<String, _>(0).unwrap_or_default(),
+    );
+    return value;
+  });
+  engine.register_fn("db_set", |key: &str, value: &str| {
+    database::execute(
+      "INSERT INTO ScriptData (Key, Value) VALUES (?, ?) ON CONFLICT(Key) DO UPDATE SET Value = excluded.Value;",
+      &[key.into(), value.into()],
+    );
+  });
+  engine.register_fn("counter", |name: &str| -> i64 {
+    return counters::get(&channel(), &name.to_lowercase()).unwrap_or_default();
+  });
+  engine.register_fn("set_counter", |name: &str, value: i64| {
+    counters::set(&channel(), &name.to_lowercase(), value);
+  });
+  engine.register_fn("notify", |text: &str| {
+    notifications::add_text_notification(text);
+  });
+  engine.register_fn(
+    "timeout",
+    |user: &str, seconds: i64, reason: &str| -> bool {
+      let duration = Some(seconds.clamp(1, i64::from(u32::MAX)) as u32);
+      return moderate(user, reason, move |channel, user, reason| {
+        return mod_commands::timeout(channel, "Script", user, duration, reason);
+      });
+    },
+  );
+  engine.register_fn("ban", |user: &str, reason: &str| -> bool {
+    return moderate(user, reason, |channel, user, reason| {
+      return mod_commands::timeout(channel, "Script", user, None, reason);
+    });
+  });
+  engine.register_fn("unban", |user: &str| -> bool {
+    return moderate(user, "", |channel, user, _| {
+      return mod_commands::unban(channel, "Script", user);
+    });
+  });
+  engine.register_fn("random", |min: i64, max: i64| -> i64 {
+    if min >= max {
+      return min;
+    }
+    return rand::thread_rng().gen_range(min..=max);
+  });
+  return engine;
+}
+
+/// Returns true if there is a script usable as chat command with provided name
+pub fn is_command(name: &str) -> bool {
+  let name = name.trim_start_matches('!').to_lowercase();
+  let scripts = SCRIPTS.lock().unwrap();
+  return scripts
+    .iter()
+    .any(|s| s.name == name && s.ast.iter_functions().any(|f| f.name == COMMAND_FUNCTION));
+}
+
+/// Runs the command defined by the script. Returns false if there is no script with that name.
+/// Text returned by the script is sent as a reply to the message.
+pub fn handle_command(msg: &Privmsg, command: &str, args: &str) -> bool {
+  let name = command.trim_start_matches('!');
+  let ast = {
+    let scripts = SCRIPTS.lock().unwrap();
+    match scripts
+      .iter()
+      .find(|s| s.name == name && s.ast.iter_functions().any(|f| f.name == COMMAND_FUNCTION))
+    {
+      Some(s) => s.ast.clone(),
+      None => return false,
+    }
+  };
+
+  let mut ctx = message_context(msg);
+  ctx.insert("args".into(), args.into());
+  if let Some(response) = call(
+    &ast,
+    name,
+    &msg.channel,
+    msg.permission(),
+    COMMAND_FUNCTION,
+    ctx,
+  ) {
+    if response.len() > 0 {
+      chat::reply(msg, &response);
+    }
+  }
+  return true;
+}
+
+/// Calls event handler of every script that defines it.
+/// Permission is the permission of the user that caused the event, it limits the moderation actions of the handlers.
+pub fn handle_event(event: Event, channel: &str, permission: Permission, ctx: Map) {
+  let scripts: Vec<(String, AST)> = {
+    let scripts = SCRIPTS.lock().unwrap();
+    scripts
+      .iter()
+      .filter(|s| s.ast.iter_functions().any(|f| f.name == event.function()))
+      .map(|s| (s.name.clone(), s.ast.clone()))
+      .collect()
+  };
+  for (name, ast) in scripts {
+    call(
+      &ast,
+      &name,
+      channel,
+      permission,
+      event.function(),
+      ctx.clone(),
+    );
+  }
+}
+
+/// Calls the function of the script with context map as the only argument.
+/// Permission is the permission of the user that started the call.
+/// Returns text returned by the function, empty if it returned something else.
+fn call(
+  ast: &AST,
+  name: &str,
+  channel: &str,
+  permission: Permission,
+  function: &str,
+  ctx: Map,
+) -> Option<String> {
+  let engine = ENGINE.get_or_init(engine);
+  CALL.with(|c| *c.borrow_mut() = (channel.to_string(), permission));
+  let mut scope = Scope::new();
+  match engine.call_fn::<Dynamic>(&mut scope, ast, function, (ctx,)) {
+    Ok(result) => return Some(result.into_string().unwrap_or_default()),
+    Err(err) => {
+      log::warn!("Script {} {} failed. {}", name, function, err);
+      return None;
+    }
+  }
+}
+
+/// Context of the chat message: user, user_id, channel, text and permission
+pub fn message_context(msg: &Privmsg) -> Map {
+  let mut ctx = Map::new();
+  ctx.insert("user".into(), msg.display_name.clone().into());
+  ctx.insert("user_id".into(), msg.user_id.clone().into());
+  ctx.insert("channel".into(), msg.channel.clone().into());
+  ctx.insert("text".into(), msg.text.clone().into());
+  ctx.insert(
+    "permission".into(),
+    format!("{:?}", msg.permission()).to_lowercase().into(),
+  );
+  return ctx;
+}
+
+/// Context of the event in the main channel: user, channel
+pub fn user_context(user: &str) -> Map {
+  let mut ctx = Map::new();
+  ctx.insert("user".into(), user.to_string().into());
+  ctx.insert(
+    "channel".into(),
+    secrets::get_data(secrets::Keys::Channel).into(),
+  );
+  return ctx;
+}
+
+/// Handles "!scripts" command, listing loaded scripts or reloading them
+pub fn handle_scripts_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  if args == "reload" {
+    load();
+  }
+  let names: Vec<String> = {
+    let scripts = SCRIPTS.lock().unwrap();
+    scripts.iter().map(|s| s.name.clone()).collect()
+  };
+  chat::reply(
+    msg,
+    &format!(
+      "Loaded scripts: {}. Use !scripts reload to load them again",
+      if names.len() > 0 {
+        names.join(", ")
+      } else {
+        "none".to_string()
+      }
+    ),
+  );
+}
@@ -24,11 +24,15 @@ impl Record {
 #[derive(Debug, PartialEq)]
 pub enum Keys {
   Channel,
+  /// Optional, comma separated names of other channels the chat bot should join
+  AdditionalChannels,
   ChannelID,
   TwitchName,
   TwitchID,
   TwitchPassowrd,
   ServerIP,
+  /// Optional, address of local test chat server, the connection to it is not encrypted
+  ChatPlaintextServer,
 }
 
 static FILE: &str = "secrets.ini";
@@ -39,11 +43,13 @@ pub fn parse() -> Result<(), ()> {
   log::info!("Parsing secrets file");
   let mut data = DATA.lock().unwrap();
   data.push(Record::new(Keys::Channel));
+  data.push(Record::new(Keys::AdditionalChannels));
   data.push(Record::new(Keys::ChannelID));
   data.push(Record::new(Keys::TwitchID));
   data.push(Record::new(Keys::TwitchName));
   data.push(Record::new(Keys::TwitchPassowrd));
   data.push(Record::new(Keys::ServerIP));
+  data.push(Record::new(Keys::ChatPlaintextServer));
 
   let (mut key, mut value): (&str, &str);
   let mut index: usize;
@@ -88,6 +94,8 @@ pub fn parse() -> Result<(), ()> {
         _set_data(&mut data, Keys::Channel, temp);
         required_info.channel.clear();
         required_info.channel.push_str(temp);
+      } else if key == format!("{:?}", Keys::AdditionalChannels) {
+        _set_data(&mut data, Keys::AdditionalChannels, &value.to_lowercase());
       } else if key == format!("{:?}", Keys::TwitchName) {
         _set_data(&mut data, Keys::TwitchName, value);
         required_info.twitch_name.clear();
@@ -106,6 +114,8 @@ pub fn parse() -> Result<(), ()> {
           required_info.server_ip.clear();
           required_info.server_ip.push_str(value);
         }
+      } else if key == format!("{:?}", Keys::ChatPlaintextServer) {
+        _set_data(&mut data, Keys::ChatPlaintextServer, value);
       } else {
         log::warn!("Key '{}' not recognized in secrets.ini", key);
       }
@@ -165,12 +175,22 @@ fn create_file() {
   if new_file.is_ok() {
     let mut content = String::new();
     content.push_str(&format!("{:?} = \n", Keys::Channel));
+    content.push_str("// Other channels the chat bot should join, separated with commas\n");
+    content.push_str(&format!("{:?} = \n", Keys::AdditionalChannels));
     content.push_str("\n");
     content.push_str(&format!("{:?} = \n", Keys::TwitchName));
     content.push_str(&format!("{:?} = \n", Keys::TwitchID));
     content.push_str(&format!("{:?} = \n", Keys::TwitchPassowrd));
     content.push_str("\n");
     content.push_str(&format!("{:?} = 127.0.0.1\n", Keys::ServerIP));
+    content.push_str("\n");
+    content.push_str(
+      "// Chat test server address, the connection is NOT encrypted, only for local testing\n",
+    );
+    content.push_str(&format!(
+      "// {:?} = 127.0.0.1:6667\n",
+      Keys::ChatPlaintextServer
+    ));
 
     new_file
       .unwrap()
@@ -0,0 +1,112 @@
+use std::{sync::Mutex, thread, time::Duration};
+
+use chrono::{DateTime, Local};
+
+use crate::{chat, helix};
+
+/// Current state of the channel stream
+struct StreamState {
+  channel: String,
+  online: bool,
+  game: String,
+  title: String,
+  started_at: Option<DateTime<Local>>,
+}
+
+/// States of the streams of joined channels
+static STREAMS: Mutex<Vec<StreamState>> = Mutex::new(Vec::new());
+/// Interval of checking the stream status
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn start() {
+  // Create stream status thread
+  thread::Builder::new()
+    .name("Stream".to_string())
+    .spawn(move || loop {
+      update();
+      thread::sleep(UPDATE_INTERVAL);
+    })
+    .expect("Spawning stream status thread failed!");
+}
+
+/// Updates stream states of all joined channels
+fn update() {
+  let channels = chat::channels();
+  let live = match helix::get_streams(&channels) {
+    Some(streams) => streams,
+    None => return,
+  };
+
+  for channel in channels {
+    let stream = live.iter().find(|s| s.user_login == channel);
+    let (game, title) = match stream {
+      Some(s) => (s.game_name.clone(), s.title.clone()),
+      None => match chat::room_id(&channel).and_then(|id| helix::get_channel_info(&id)) {
+        Some(info) => (info.game_name, info.title),
+        None => (String::new(), String::new()),
+      },
+    };
+    let started_at = stream.and_then(|s| {
+      DateTime::parse_from_rfc3339(&s.started_at)
+        .ok()
+        .map(|t| t.with_timezone(&Local))
+    });
+
+    let mut streams = STREAMS.lock().unwrap();
+    let index = match streams.iter().position(|s| s.channel == channel) {
+      Some(i) => i,
+      None => {
+        streams.push(StreamState {
+          channel: channel.clone(),
+          online: false,
+          game: String::new(),
+          title: String::new(),
+          started_at: None,
+        });
+        streams.len() - 1
+      }
+    };
+    let state = &mut streams[index];
+    if state.online != stream.is_some() {
+      if stream.is_some() {
+        log::info!("#{} went live, playing {}", channel, game);
+        chat::greetings::stream_started(&channel);
+      } else {
+        log::info!("#{} went offline", channel);
+      }
+    }
+    state.online = stream.is_some();
+    if game.len() > 0 {
+      state.game = game;
+    }
+    if title.len() > 0 {
+      state.title = title;
+    }
+    state.started_at = started_at;
+  }
+}
+
+/// Returns true if the channel is streaming
+pub fn is_online(channel: &str) -> bool {
+  let streams = STREAMS.lock().unwrap();
+  return streams.iter().any(|s| s.channel == channel && s.online);
+}
+
+/// Returns current (or last known) category of the channel
+pub fn game(channel: &str) -> Option<String> {
+  let streams = STREAMS.lock().unwrap();
+  return streams
+    .iter()
+    .find(|s| s.channel == channel && s.game.len() > 0)
+    .map(|s| s.game.clone());
+}
+
+/// Returns for how long the channel is streaming, None if it's offline
+pub fn uptime(channel: &str) -> Option<chrono::Duration> {
+  let streams = STREAMS.lock().unwrap();
+  let started_at = streams
+    .iter()
+    .find(|s| s.channel == channel && s.online)?
+    .started_at?;
+  return Some(Local::now() - started_at);
+}
@@ -0,0 +1,377 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use chrono::{DateTime, FixedOffset, Local};
+use rand::Rng;
+
+use crate::{
+  chat::{self, counters, Permission, Privmsg},
+  database, helix, points, secrets, stream,
+};
+
+/// Names of the texts used by the bot that can be customized
+const DEFAULT_NAMES: &[&str] = &[
+  "FollowChat",
+  "FollowDisplayed",
+  "SubscriptionDisplayed",
+  "ShoutoutChat",
+  "FirstChatterChat",
+  "FirstChatterDisplayed",
+  "ReturningChatterChat",
+  "ReturningChatterDisplayed",
+];
+
+/// Follow of the user checked using Twitch API
+struct CachedFollow {
+  channel: String,
+  user_id: String,
+  followed_at: Option<DateTime<FixedOffset>>,
+  checked: Instant,
+}
+
+/// Game of the channel checked using Twitch API
+struct CachedGame {
+  user_id: String,
+  game: String,
+  checked: Instant,
+}
+
+/// Recently checked follows
+static FOLLOWS: Mutex<Vec<CachedFollow>> = Mutex::new(Vec::new());
+/// Recently checked games of the channels
+static GAMES: Mutex<Vec<CachedGame>> = Mutex::new(Vec::new());
+/// How long the checked follows and games are remembered
+const HELIX_CACHE: Duration = Duration::from_secs(10 * 60);
+/// Variables that need Twitch API requests to get their values
+const HELIX_VARIABLES: &[&str] = &["$(followage", "$(game"];
+
+/// Data available to the template variables
+pub struct Context {
+  pub channel: String,
+  /// Display name of the user that triggered the response
+  pub user: String,
+  pub user_id: String,
+  /// Arguments of the chat command
+  pub args: String,
+}
+
+impl Context {
+  /// Context of the chat command
+  pub fn from_message(msg: &Privmsg, args: &str) -> Self {
+    return Self {
+      channel: msg.channel.clone(),
+      user: msg.display_name.clone(),
+      user_id: msg.user_id.clone(),
+      args: args.to_string(),
+    };
+  }
+
+  /// Context of the event that happened in the main channel
+  pub fn from_user(user: &str) -> Self {
+    return Self {
+      channel: secrets::get_data(secrets::Keys::Channel),
+      user: user.to_string(),
+      user_id: String::new(),
+      args: String::new(),
+    };
+  }
+
+  /// The user the command was targeted at (first argument), the user itself if there are no arguments
+  fn touser(&self) -> String {
+    return match self.args.split_whitespace().next() {
+      Some(user) => user.trim_start_matches('@').to_string(),
+      None => self.user.clone(),
+    };
+  }
+}
+
+/// Returns customized text from the database, `default` if it's not set
+pub fn get(name: &str, default: &str) -> String {
+  let mut text = default.to_string();
+  database::query(
+    "SELECT Text FROM Templates WHERE Name = ? LIMIT 1;",
+    &[name.into()],
+    |row| text = row.read::<String, _>(0).unwrap_or_default(),
+  );
+  return text;
+}
+
+/// Replaces variables like $(user) in the text.
+/// Supported variables: $(user), $(touser), $(args), $(count name), $(uptime), $(random min max), $(channel), $(followage), $(game).
+/// Unknown variables are left unchanged.
+pub fn render(text: &str, ctx: &Context) -> String {
+  let mut result = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find("$(") {
+    result.push_str(&rest[..start]);
+    let end = match rest[start..].find(')') {
+      Some(end) => start + end,
+      None => {
+        rest = &rest[start..];
+        break;
+      }
+    };
+    let variable = &rest[(start + 2)..end];
+    match value(variable, ctx) {
+      Some(value) => result.push_str(&value),
+      None => result.push_str(&rest[start..=end]),
+    }
+    rest = &rest[(end + 1)..];
+  }
+  result.push_str(rest);
+  return result;
+}
+
+/// Returns true if rendering the text needs Twitch API requests, so it can take a while
+pub fn uses_helix(text: &str) -> bool {
+  let text = text.to_lowercase();
+  return HELIX_VARIABLES.iter().any(|v| text.contains(v));
+}
+
+/// Returns the value of the variable, None if the variable is unknown
+fn value(variable: &str, ctx: &Context) -> Option<String> {
+  let mut words = variable.split_whitespace();
+  let name = words.next()?.to_lowercase();
+  let params: Vec<&str> = words.collect();
+  match name.as_str() {
+    "user" => return Some(ctx.user.clone()),
+    "touser" => return Some(ctx.touser()),
+    "args" => return Some(ctx.args.clone()),
+    "channel" => return Some(ctx.channel.clone()),
+    "count" => {
+      let counter = params.first()?.to_lowercase();
+      return Some(
+        counters::get(&ctx.channel, &counter)
+          .unwrap_or_default()
+          .to_string(),
+      );
+    }
+    "random" => {
+      let (min, max) = match params.len() {
+        0 => (1, 100),
+        1 => (1, params[0].parse::<i64>().ok()?),
+        _ => (
+          params[0].parse::<i64>().ok()?,
+          params[1].parse::<i64>().ok()?,
+        ),
+      };
+      if min > max {
+        return Some(min.to_string());
+      }
+      return Some(rand::thread_rng().gen_range(min..=max).to_string());
+    }
+    "uptime" => {
+      return Some(match stream::uptime(&ctx.channel) {
+        Some(uptime) => format_duration(uptime.num_seconds()),
+        None => "offline".to_string(),
+      });
+    }
+    "followage" => return Some(followage(ctx)),
+    "game" => {
+      return Some(
+        touser_id(ctx)
+          .and_then(|id| game(&id))
+          .filter(|g| g.len() > 0)
+          .unwrap_or("nothing yet".to_string()),
+      );
+    }
+    _ => return None,
+  }
+}
+
+/// Returns the user ID of the target user (see `Context::touser`), known viewers are not requested from Twitch
+fn touser_id(ctx: &Context) -> Option<String> {
+  let touser = ctx.touser();
+  if touser == ctx.user && ctx.user_id.len() > 0 {
+    return Some(ctx.user_id.clone());
+  }
+  return points::find_viewer(&touser)
+    .map(|v| v.user_id)
+    .or_else(|| helix::get_user_id(&touser));
+}
+
+/// Returns the game last played in the channel of the user, recently checked games are not requested again
+fn game(user_id: &str) -> Option<String> {
+  let now = Instant::now();
+  {
+    let mut games = GAMES.lock().unwrap();
+    games.retain(|g| now - g.checked < HELIX_CACHE);
+    if let Some(g) = games.iter().find(|g| g.user_id == user_id) {
+      return Some(g.game.clone());
+    }
+  }
+  let game = helix::get_channel_info(user_id)?.game_name;
+  GAMES.lock().unwrap().push(CachedGame {
+    user_id: user_id.to_string(),
+    game: game.clone(),
+    checked: now,
+  });
+  return Some(game);
+}
+
+/// Returns for how long the target user (see `Context::touser`) is following the channel
+fn followage(ctx: &Context) -> String {
+  let followed_at = touser_id(ctx).and_then(|id| followed_at(&ctx.channel, &id));
+  match followed_at {
+    Some(time) => {
+      let days = (Local::now() - time.with_timezone(&Local)).num_days();
+      let mut parts = Vec::new();
+      for (count, unit) in [
+        (days / 365, "year"),
+        (days % 365 / 30, "month"),
+        (days % 365 % 30, "day"),
+      ] {
+        if count > 0 {
+          parts.push(format!(
+            "{} {}{}",
+            count,
+            unit,
+            if count > 1 { "s" } else { "" }
+          ));
+        }
+      }
+      if parts.len() == 0 {
+        return "less than a day".to_string();
+      }
+      return parts.join(" ");
+    }
+    None => return "not following".to_string(),
+  }
+}
+
+/// Returns the time the user followed the channel, recently checked follows are not requested again
+fn followed_at(channel: &str, user_id: &str) -> Option<DateTime<FixedOffset>> {
+  let now = Instant::now();
+  {
+    let mut follows = FOLLOWS.lock().unwrap();
+    follows.retain(|f| now - f.checked < HELIX_CACHE);
+    if let Some(f) = follows
+      .iter()
+      .find(|f| f.channel == channel && f.user_id == user_id)
+    {
+      return f.followed_at;
+    }
+  }
+  let broadcaster_id = chat::room_id(channel)?;
+  let followed_at = helix::get_followed_at(&broadcaster_id, user_id)
+    .and_then(|f| DateTime::parse_from_rfc3339(&f).ok());
+  FOLLOWS.lock().unwrap().push(CachedFollow {
+    channel: channel.to_string(),
+    user_id: user_id.to_string(),
+    followed_at,
+    checked: now,
+  });
+  return followed_at;
+}
+
+/// Formats duration in seconds like "1h 5m"
+fn format_duration(seconds: i64) -> String {
+  let (hours, minutes) = (seconds / 3600, seconds % 3600 / 60);
+  if hours > 0 {
+    return format!("{}h {}m", hours, minutes);
+  }
+  return format!("{}m", minutes);
+}
+
+/// Handles "!template" command showing or changing customized text
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  if msg.permission() < Permission::Moderator {
+    return;
+  }
+  let (name, text) = match args.find(' ') {
+    Some(idx) => (&args[..idx], args[(idx + 1)..].trim()),
+    None => (args, ""),
+  };
+  let answer = if name.len() == 0 {
+    let mut names = Vec::new();
+    database::query("SELECT Name FROM Templates;", &[], |row| {
+      names.push(row.read::<String, _>(0).unwrap_or_default())
+    });
+    format!(
+      "Usage: !template <name> [text]. Customized: {}. Default names: {}",
+      if names.len() > 0 {
+        names.join(", ")
+      } else {
+        "none".to_string()
+      },
+      DEFAULT_NAMES.join(", ")
+    )
+  } else if let Some(name) = DEFAULT_NAMES.iter().find(|n| n.eq_ignore_ascii_case(name)) {
+    if text.len() == 0 {
+      format!("{}: {}", name, get(name, "(not set)"))
+    } else if text == "default" {
+      database::execute("DELETE FROM Templates WHERE Name = ?;", &[(*name).into()]);
+      format!("{} restored to default", name)
+    } else {
+      database::execute(
+        "INSERT INTO Templates (Name, Text) VALUES (?, ?) ON CONFLICT(Name) DO UPDATE SET Text = excluded.Text;",
+        &[(*name).into(), text.into()],
+      );
+      format!("{} changed", name)
+    }
+  } else {
+    format!(
+      "Unknown template {}, available: {}",
+      name,
+      DEFAULT_NAMES.join(", ")
+    )
+  };
+  chat::reply(msg, &answer);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn context(args: &str) -> Context {
+    return Context {
+      channel: "channel".to_string(),
+      user: "User".to_string(),
+      user_id: "1".to_string(),
+      args: args.to_string(),
+    };
+  }
+
+  #[test]
+  fn renders_variables() {
+    let ctx = context("@Other more args");
+    assert_eq!(render("Hi $(user)!", &ctx), "Hi User!");
+    assert_eq!(render("$(touser) in $(channel)", &ctx), "Other in channel");
+    assert_eq!(render("$(args)", &ctx), "@Other more args");
+    assert_eq!(render("$(USER)", &ctx), "User");
+    assert_eq!(render("$(touser)", &context("")), "User");
+  }
+
+  #[test]
+  fn renders_random() {
+    let ctx = context("");
+    assert_eq!(render("$(random 5 5)", &ctx), "5");
+    assert_eq!(render("$(random 7 3)", &ctx), "7");
+    let value: i64 = render("$(random 3)", &ctx).parse().unwrap();
+    assert!((1..=3).contains(&value));
+    assert_eq!(render("$(random x)", &ctx), "$(random x)");
+  }
+
+  #[test]
+  fn keeps_unknown_and_unfinished_variables() {
+    let ctx = context("");
+    assert_eq!(render("$(unknown) $(user)", &ctx), "$(unknown) User");
+    assert_eq!(render("$() $(user", &ctx), "$() $(user");
+    assert_eq!(render("no variables", &ctx), "no variables");
+  }
+
+  #[test]
+  fn detects_helix_variables() {
+    assert!(uses_helix("playing $(game)"));
+    assert!(uses_helix("following for $(FollowAge)"));
+    assert!(!uses_helix("$(user) $(uptime)"));
+  }
+
+  #[test]
+  fn formats_durations() {
+    assert_eq!(format_duration(59), "0m");
+    assert_eq!(format_duration(3 * 3600 + 5 * 60), "3h 5m");
+  }
+}
@@ -0,0 +1,194 @@
+use std::{
+  io::Write,
+  process::{Command, Stdio},
+  sync::Mutex,
+};
+
+use crate::{
+  chat::{self, Permission, Privmsg},
+  database, notifications, secrets,
+};
+
+/// Text to speech engine rendering the text into WAV file
+pub trait Backend: Send {
+  fn name(&self) -> &str;
+  /// Renders the text with provided voice, empty voice means the default one
+  fn render(&self, text: &str, voice: &str) -> Result<Vec<u8>, String>;
+}
+
+/// Offline espeak (or compatible espeak-ng) command line engine
+pub struct Espeak {
+  pub program: String,
+}
+
+impl Backend for Espeak {
+  fn name(&self) -> &str {
+    return &self.program;
+  }
+
+  fn render(&self, text: &str, voice: &str) -> Result<Vec<u8>, String> {
+    let mut command = Command::new(&self.program);
+    command.arg("--stdout");
+    if voice.len() > 0 {
+      command.args(["-v", voice]);
+    }
+    // Text is passed through stdin so it can't be interpreted as an argument
+    let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin
+        .write_all(text.as_bytes())
+        .map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() || output.stdout.len() == 0 {
+      return Err(format!("{} exited with {}", self.program, output.status));
+    }
+    return Ok(output.stdout);
+  }
+}
+
+/// Used text to speech engine
+static BACKEND: Mutex<Option<Box<dyn Backend>>> = Mutex::new(None);
+/// Recently rendered audio files (ID, WAV data)
+static AUDIO: Mutex<Vec<(u32, Vec<u8>)>> = Mutex::new(Vec::new());
+/// ID of the last rendered audio file
+static LAST_ID: Mutex<u32> = Mutex::new(0);
+/// Number of rendered audio files kept in memory
+const AUDIO_KEPT: usize = 10;
+/// Maximum number of characters that are read, longer text is shortened
+const MAX_LENGTH: usize = 200;
+/// Title of the channel point reward that reads the text entered by the viewer
+pub const REWARD_TITLE: &str = "Text to speech";
+
+/// Sets espeak as the default text to speech engine
+pub fn start() {
+  set_backend(Box::new(Espeak {
+    program: "espeak-ng".to_string(),
+  }));
+}
+
+/// Changes used text to speech engine
+pub fn set_backend(backend: Box<dyn Backend>) {
+  log::info!("Text to speech engine: {}", backend.name());
+  *BACKEND.lock().unwrap() = Some(backend);
+}
+
+/// Checks the text against the banned phrases of the channel and shortens it to the maximum length.
+/// Returns None if the text shouldn't be read.
+pub fn filter(channel: &str, text: &str) -> Option<String> {
+  let text = text.trim();
+  if text.len() == 0 || chat::is_banned(channel, text) {
+    return None;
+  }
+  return Some(text.chars().take(MAX_LENGTH).collect());
+}
+
+/// Renders the text with the voice of the user. Returns the path the overlay can load it from.
+pub fn render(text: &str, user_id: &str) -> Option<String> {
+  let voice = voice(user_id);
+  let data = {
+    let backend = BACKEND.lock().unwrap();
+    match backend.as_ref()?.render(text, &voice) {
+      Ok(data) => data,
+      Err(err) => {
+        log::warn!("Couldn't render text to speech. {}", err);
+        return None;
+      }
+    }
+  };
+
+  let id = {
+    let mut last_id = LAST_ID.lock().unwrap();
+    *last_id += 1;
+    *last_id
+  };
+  let mut audio = AUDIO.lock().unwrap();
+  audio.push((id, data));
+  while audio.len() > AUDIO_KEPT {
+    audio.remove(0);
+  }
+  return Some(format!("tts?id={}", id));
+}
+
+/// Returns rendered audio file with provided ID
+pub fn get_audio(id: u32) -> Option<Vec<u8>> {
+  let audio = AUDIO.lock().unwrap();
+  return audio.iter().find(|a| a.0 == id).map(|a| a.1.clone());
+}
+
+/// Returns the voice chosen by the user, empty if it's not set
+fn voice(user_id: &str) -> String {
+  let mut voice = String::new();
+  if user_id.len() == 0 {
+    return voice;
+  }
+  database::query(
+    "SELECT Voice FROM Viewers WHERE UserID = ?;",
+    &[user_id.into()],
+    |row| voice = row.read::<String, _>(0).unwrap_or_default(),
+  );
+  return voice;
+}
+
+/// Reads the text of channel point redemption
+pub fn redeemed(user_id: &str, user_name: &str, text: &str) {
+  match filter(&secrets::get_data(secrets::Keys::Channel), text) {
+    Some(text) => notifications::add_tts_notification(user_id, &text),
+    None => log::info!("Text to speech of {} was filtered: {}", user_name, text),
+  }
+}
+
+/// Handles "!tts" command reading the text or changing the voice of the user
+pub fn handle_command(msg: &Privmsg, args: &str) {
+  let mut words = args.splitn(2, ' ');
+  if words.next() == Some("voice") {
+    if !chat::check_access(msg, "tts voice", Permission::Everyone, 0, 10) {
+      return;
+    }
+    let voice = words.next().unwrap_or_default().trim();
+    if voice.len() == 0 {
+      let current = self::voice(&msg.user_id);
+      chat::reply(
+        msg,
+        &format!(
+          "Your voice is {}. Change it with !tts voice <name|default>",
+          if current.len() > 0 {
+            &current
+          } else {
+            "default"
+          }
+        ),
+      );
+      return;
+    }
+    if voice.len() > 50
+      || !voice
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '/'))
+    {
+      chat::reply(msg, "That's not a valid voice name");
+      return;
+    }
+    let voice = if voice == "default" { "" } else { voice };
+    database::execute(
+      "UPDATE Viewers SET Voice = ? WHERE UserID = ?;",
+      &[voice.into(), msg.user_id.as_str().into()],
+    );
+    chat::reply(msg, "Your voice was changed");
+    return;
+  }
+
+  if !chat::check_access(msg, "tts", Permission::Subscriber, 0, 60) {
+    return;
+  }
+  match filter(&msg.channel, args) {
+    Some(text) => notifications::add_tts_notification(&msg.user_id, &text),
+    None if args.len() == 0 => chat::reply(msg, "Usage: !tts <text>, !tts voice <name>"),
+    None => chat::reply(msg, "That can't be read"),
+  }
+}